@@ -0,0 +1,194 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use serde_json::{Map, Value};
+use sha2::Sha256;
+
+use crate::crypto;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Field carrying the base64 HMAC-SHA256 of the rest of the message.
+const SIGNATURE_FIELD: &str = "sig";
+
+/// Optional credentials for a protected room.
+#[derive(Debug, Clone, Default)]
+pub struct RoomCredentials {
+    /// Sent as `Authorization: Bearer` on the WebSocket upgrade.
+    pub token: Option<String>,
+    /// Shared secret used to sign and verify every message.
+    pub passphrase: Option<String>,
+    /// Also encrypt playback messages with a key derived from the passphrase.
+    pub encrypt: bool,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum VerifyError {
+    Unsigned,
+    BadSignature,
+}
+
+impl std::fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Unsigned => write!(f, "message is not signed"),
+            Self::BadSignature => write!(f, "signature does not match room passphrase"),
+        }
+    }
+}
+
+/// HMAC key for one room. Stretched from the passphrase rather than using
+/// it directly, so a signed message is no shortcut to guessing it.
+#[derive(Clone)]
+pub struct SigningKey {
+    mac: HmacSha256,
+    room: String,
+}
+
+impl SigningKey {
+    /// Salted apart from the end-to-end key, so the two are unrelated.
+    pub fn derive(passphrase: &str, room: &str) -> Self {
+        let key = crypto::stretch(passphrase, &format!("dragonfox-mediasync-sign/{}", room));
+        Self {
+            mac: HmacSha256::new_from_slice(&key).expect("HMAC accepts keys of any length"),
+            room: room.to_string(),
+        }
+    }
+
+    /// MAC over the room and the message, so a message signed for one room
+    /// can't be replayed into another.
+    fn mac(&self, map: &Map<String, Value>) -> HmacSha256 {
+        let mut mac = self.mac.clone();
+        mac.update(Value::String(self.room.clone()).to_string().as_bytes());
+        mac.update(canonical(&Value::Object(map.clone())).as_bytes());
+        mac
+    }
+}
+
+/// Adds a `sig` field signing every other field of `message`.
+pub fn sign(message: &mut Value, key: &SigningKey) {
+    let Value::Object(map) = message else {
+        return;
+    };
+    map.remove(SIGNATURE_FIELD);
+
+    let signature = STANDARD.encode(key.mac(map).finalize().into_bytes());
+    map.insert(SIGNATURE_FIELD.into(), Value::String(signature));
+}
+
+/// Checks and strips the `sig` field of a received message.
+pub fn verify(message: &mut Value, key: &SigningKey) -> Result<(), VerifyError> {
+    let Value::Object(map) = message else {
+        return Err(VerifyError::Unsigned);
+    };
+    let signature = match map.remove(SIGNATURE_FIELD) {
+        Some(Value::String(s)) => s,
+        _ => return Err(VerifyError::Unsigned),
+    };
+    let signature = STANDARD
+        .decode(signature)
+        .map_err(|_| VerifyError::BadSignature)?;
+
+    key.mac(map)
+        .verify_slice(&signature)
+        .map_err(|_| VerifyError::BadSignature)
+}
+
+/// JSON with object keys sorted at every level, so both ends hash the same
+/// bytes regardless of field order on the wire.
+fn canonical(value: &Value) -> String {
+    match value {
+        Value::Object(map) => {
+            let mut keys: Vec<&String> = map.keys().collect();
+            keys.sort();
+            let fields: Vec<String> = keys
+                .into_iter()
+                .map(|k| format!("{}:{}", Value::String(k.clone()), canonical(&map[k])))
+                .collect();
+            format!("{{{}}}", fields.join(","))
+        }
+        Value::Array(items) => {
+            let items: Vec<String> = items.iter().map(canonical).collect();
+            format!("[{}]", items.join(","))
+        }
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn signed_messages_verify() {
+        let original = json!({"type": "seek", "position": 5, "clientId": "a", "seq": 1});
+        let key = SigningKey::derive("secret", "room");
+        let mut message = original.clone();
+        sign(&mut message, &key);
+        assert!(message.get(SIGNATURE_FIELD).is_some());
+
+        assert_eq!(verify(&mut message, &key), Ok(()));
+        // The signature is stripped again
+        assert_eq!(message, original);
+    }
+
+    #[test]
+    fn field_order_does_not_matter() {
+        let mut message: Value =
+            serde_json::from_str(r#"{"type":"x","extra":{"b":1,"a":[{"d":3,"c":4}]}}"#).unwrap();
+        let key = SigningKey::derive("secret", "room");
+        sign(&mut message, &key);
+
+        // The same message as another client might lay it out on the wire
+        let reordered = format!(
+            r#"{{"sig":{},"extra":{{"a":[{{"c":4,"d":3}}],"b":1}},"type":"x"}}"#,
+            message[SIGNATURE_FIELD]
+        );
+        let mut reordered: Value = serde_json::from_str(&reordered).unwrap();
+        assert_eq!(verify(&mut reordered, &key), Ok(()));
+
+        assert_eq!(
+            canonical(&reordered),
+            r#"{"extra":{"a":[{"c":4,"d":3}],"b":1},"type":"x"}"#
+        );
+    }
+
+    #[test]
+    fn tampering_and_wrong_keys_are_caught() {
+        let key = SigningKey::derive("secret", "room");
+        let mut message = json!({"type": "play", "clientId": "a", "seq": 1});
+        sign(&mut message, &key);
+
+        assert_eq!(
+            verify(&mut message.clone(), &SigningKey::derive("guess", "room")),
+            Err(VerifyError::BadSignature)
+        );
+        // Replayed into another room with the same passphrase
+        assert_eq!(
+            verify(&mut message.clone(), &SigningKey::derive("secret", "other")),
+            Err(VerifyError::BadSignature)
+        );
+
+        message["seq"] = json!(2);
+        assert_eq!(
+            verify(&mut message.clone(), &key),
+            Err(VerifyError::BadSignature)
+        );
+
+        message[SIGNATURE_FIELD] = json!("not base64!");
+        assert_eq!(
+            verify(&mut message.clone(), &key),
+            Err(VerifyError::BadSignature)
+        );
+    }
+
+    #[test]
+    fn unsigned_messages_are_reported() {
+        let key = SigningKey::derive("secret", "room");
+        let mut message = json!({"type": "play"});
+        assert_eq!(verify(&mut message, &key), Err(VerifyError::Unsigned));
+        assert_eq!(verify(&mut json!([1]), &key), Err(VerifyError::Unsigned));
+    }
+}
@@ -0,0 +1,378 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use futures_util::{SinkExt, StreamExt};
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Runtime};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
+use tokio_tungstenite::accept_hdr_async;
+use tokio_tungstenite::tungstenite::handshake::server::{ErrorResponse, Request, Response};
+use tokio_tungstenite::tungstenite::http::{header, StatusCode};
+use tokio_tungstenite::tungstenite::Message as WsMessage;
+use tracing::{debug, error, info, warn};
+
+use crate::media::{BackendKind, PlaybackState, PlayerChange};
+use crate::protocol;
+use crate::state::AppState;
+use crate::watcher;
+
+/// Localhost port the browser extension connects to.
+pub const BRIDGE_PORT: u16 = 7420;
+
+/// Only extension pages may connect, so an arbitrary website can't drive
+/// playback through the bridge.
+const EXTENSION_ORIGINS: &[&str] = &["chrome-extension://", "moz-extension://"];
+
+/// Sent to the extension: what to do with the active video.
+#[derive(Debug, Clone, Serialize)]
+#[serde(
+    tag = "type",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+pub enum BridgeCommand {
+    Play,
+    Pause,
+    Toggle,
+    Seek { position: i64 },
+    Next,
+    Previous,
+    Stop,
+    VolumeUp,
+    VolumeDown,
+    Mute,
+}
+
+/// Sent by the extension about the active video.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(
+    tag = "type",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+enum ExtensionMessage {
+    /// Current state, sent on connect and whenever the active video changes.
+    Status {
+        playing: bool,
+        #[serde(default)]
+        position: Option<i64>,
+    },
+    /// The user started playback in the page.
+    Played {
+        #[serde(default)]
+        position: Option<i64>,
+    },
+    Paused {
+        #[serde(default)]
+        position: Option<i64>,
+    },
+    Seeked {
+        position: i64,
+    },
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BridgeStatus {
+    port: u16,
+    connections: usize,
+}
+
+/// Connected extension instances and the last state they reported.
+#[derive(Default)]
+pub struct Bridge {
+    clients: RwLock<Vec<(u64, UnboundedSender<BridgeCommand>)>>,
+    next_id: AtomicU64,
+    /// Reported state and the local time it was reported at.
+    status: RwLock<Option<(PlaybackState, i64)>>,
+}
+
+impl Bridge {
+    /// Sends a command to the most recently connected extension, which is
+    /// the browser the user most likely just opened.
+    pub fn send(&self, command: BridgeCommand) -> Result<(), String> {
+        let clients = self.clients.read();
+        let (_, sender) = clients.last().ok_or("No browser extension is connected")?;
+        sender.send(command).map_err(|e| e.to_string())
+    }
+
+    /// Last reported state, with the position advanced by the time since
+    /// the report if the video is playing.
+    pub fn status(&self) -> Option<PlaybackState> {
+        let (mut status, reported_at) = (*self.status.read())?;
+        if status.playing {
+            status.position_ms = status
+                .position_ms
+                .map(|p| p + (protocol::now_ms() - reported_at).max(0));
+        }
+        Some(status)
+    }
+
+    /// Where the bridge listens and how many extensions are connected.
+    pub fn summary(&self) -> BridgeStatus {
+        BridgeStatus {
+            port: BRIDGE_PORT,
+            connections: self.clients.read().len(),
+        }
+    }
+
+    /// Forgets a disconnected extension, and once the last one is gone,
+    /// the state it reported; there's no video left to extrapolate.
+    fn disconnect(&self, id: u64) {
+        let mut clients = self.clients.write();
+        clients.retain(|(client, _)| *client != id);
+        if clients.is_empty() {
+            *self.status.write() = None;
+        }
+    }
+
+    fn set_status(&self, playing: bool, position_ms: Option<i64>) {
+        *self.status.write() = Some((
+            PlaybackState {
+                playing,
+                position_ms,
+            },
+            protocol::now_ms(),
+        ));
+    }
+}
+
+/// Starts listening for the extension on localhost.
+pub fn start(app: AppHandle, state: Arc<AppState>) {
+    tauri::async_runtime::spawn(async move {
+        let listener = match TcpListener::bind(("127.0.0.1", BRIDGE_PORT)).await {
+            Ok(l) => l,
+            Err(e) => {
+                error!("Browser bridge could not bind port {}: {}", BRIDGE_PORT, e);
+                return;
+            }
+        };
+        info!("Browser bridge listening on 127.0.0.1:{}", BRIDGE_PORT);
+
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => {
+                    let (app, state) = (app.clone(), state.clone());
+                    tauri::async_runtime::spawn(async move {
+                        handle_client(app, state, stream).await;
+                    });
+                }
+                Err(e) => warn!("Browser bridge accept failed: {}", e),
+            }
+        }
+    });
+}
+
+/// Handshake callback: only extension pages may connect.
+// The error type is fixed by tungstenite
+#[allow(clippy::result_large_err)]
+fn check_origin(request: &Request, response: Response) -> Result<Response, ErrorResponse> {
+    let origin = request
+        .headers()
+        .get(header::ORIGIN)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+    if EXTENSION_ORIGINS.iter().any(|o| origin.starts_with(o)) {
+        Ok(response)
+    } else {
+        let mut reject = ErrorResponse::new(Some("browser extensions only".into()));
+        *reject.status_mut() = StatusCode::FORBIDDEN;
+        Err(reject)
+    }
+}
+
+async fn handle_client(app: AppHandle, state: Arc<AppState>, stream: TcpStream) {
+    let ws_stream = match accept_hdr_async(stream, check_origin).await {
+        Ok(s) => s,
+        Err(e) => {
+            warn!("Rejected browser bridge connection: {}", e);
+            return;
+        }
+    };
+    let (mut write, mut read) = ws_stream.split();
+
+    let bridge = state.bridge.clone();
+    let id = bridge.next_id.fetch_add(1, Ordering::Relaxed);
+    let (tx, mut rx) = unbounded_channel::<BridgeCommand>();
+    bridge.clients.write().push((id, tx));
+    info!("Browser extension connected ({})", id);
+    emit_status(&app, &bridge);
+
+    loop {
+        tokio::select! {
+            Some(command) = rx.recv() => {
+                let json = match serde_json::to_string(&command) {
+                    Ok(j) => j,
+                    Err(e) => {
+                        error!("Failed to serialize bridge command: {}", e);
+                        continue;
+                    }
+                };
+                if let Err(e) = write.send(WsMessage::Text(json.into())).await {
+                    warn!("Failed to send to browser extension: {}", e);
+                    break;
+                }
+            }
+            Some(result) = read.next() => {
+                match result {
+                    Ok(WsMessage::Text(text)) => handle_report(&app, &state, &text),
+                    Ok(WsMessage::Close(_)) => break,
+                    Err(e) => {
+                        warn!("Browser bridge error: {}", e);
+                        break;
+                    }
+                    _ => {}
+                }
+            }
+            else => break,
+        }
+    }
+
+    bridge.disconnect(id);
+    info!("Browser extension disconnected ({})", id);
+    emit_status(&app, &bridge);
+}
+
+/// Records what the extension reports and passes changes on to the
+/// watcher, which tells the room about the ones the user made.
+fn handle_report<R: Runtime>(app: &AppHandle<R>, state: &Arc<AppState>, text: &str) {
+    let report: ExtensionMessage = match serde_json::from_str(text) {
+        Ok(r) => r,
+        Err(e) => {
+            warn!("Failed to parse browser extension message: {}", e);
+            return;
+        }
+    };
+    debug!("Browser extension reported {:?}", report);
+
+    let bridge = &state.bridge;
+    let change = match report {
+        ExtensionMessage::Status { playing, position } => {
+            bridge.set_status(playing, position);
+            return;
+        }
+        ExtensionMessage::Played { position } => {
+            bridge.set_status(true, position);
+            PlayerChange::Played
+        }
+        ExtensionMessage::Paused { position } => {
+            bridge.set_status(false, position);
+            PlayerChange::Paused
+        }
+        ExtensionMessage::Seeked { position } => {
+            let playing = bridge.status().is_some_and(|s| s.playing);
+            bridge.set_status(playing, Some(position));
+            PlayerChange::Seeked {
+                position_ms: position,
+            }
+        }
+    };
+
+    // Only an enabled browser backend speaks for the room; otherwise the
+    // page is just another tab the user happens to have open
+    if state.has_media(BackendKind::Browser) {
+        watcher::report(app, state, change);
+    }
+}
+
+fn emit_status<R: Runtime>(app: &AppHandle<R>, bridge: &Bridge) {
+    let _ = app.emit("bridge-status", bridge.summary());
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use tauri::test::mock_app;
+
+    use super::*;
+    use crate::media::MediaTarget;
+    use crate::protocol::{Message, MessageKind};
+    use crate::state::ConnectionStatus;
+
+    fn handshake(origin: Option<&str>) -> Result<Response, ErrorResponse> {
+        let mut request = Request::builder().uri("ws://127.0.0.1:7420/");
+        if let Some(origin) = origin {
+            request = request.header(header::ORIGIN, origin);
+        }
+        check_origin(&request.body(()).unwrap(), Response::default())
+    }
+
+    #[test]
+    fn only_extension_pages_may_connect() {
+        assert!(handshake(Some("chrome-extension://abcdef")).is_ok());
+        assert!(handshake(Some("moz-extension://1234-5678")).is_ok());
+
+        for origin in [Some("https://example.com"), Some("null"), None] {
+            let rejected = handshake(origin).unwrap_err();
+            assert_eq!(rejected.status(), StatusCode::FORBIDDEN);
+        }
+    }
+
+    #[test]
+    fn reports_update_the_status() {
+        let app = mock_app();
+        let state = Arc::new(AppState::new());
+        let report = |msg: serde_json::Value| handle_report(app.handle(), &state, &msg.to_string());
+
+        report(json!({"type": "status", "playing": false, "position": 1_000}));
+        assert_eq!(
+            state.bridge.status(),
+            Some(PlaybackState {
+                playing: false,
+                position_ms: Some(1_000),
+            })
+        );
+
+        report(json!({"type": "seeked", "position": 5_000}));
+        report(json!({"type": "nonsense"}));
+        assert_eq!(state.bridge.status().unwrap().position_ms, Some(5_000));
+
+        report(json!({"type": "played"}));
+        let status = state.bridge.status().unwrap();
+        assert!(status.playing);
+        assert_eq!(status.position_ms, None);
+    }
+
+    #[test]
+    fn user_changes_reach_the_room_only_with_the_browser_backend() {
+        let app = mock_app();
+        let state = Arc::new(AppState::new());
+        let (tx, mut rx) = unbounded_channel::<Message>();
+        state.set_sender(Some(tx));
+        state.set_status(ConnectionStatus::Connected {
+            room: "room".into(),
+            latency_ms: None,
+        });
+        let report = |msg: serde_json::Value| handle_report(app.handle(), &state, &msg.to_string());
+
+        report(json!({"type": "played", "position": 0}));
+        assert!(rx.try_recv().is_err());
+
+        state
+            .set_media_targets(vec![MediaTarget::new(BackendKind::Browser)])
+            .unwrap();
+        report(json!({"type": "paused", "position": 0}));
+        assert_eq!(rx.try_recv().unwrap().kind, MessageKind::Pause);
+    }
+
+    #[test]
+    fn status_is_forgotten_with_the_last_extension() {
+        let bridge = Bridge::default();
+        for id in [1, 2] {
+            let (tx, _) = unbounded_channel();
+            bridge.clients.write().push((id, tx));
+        }
+        bridge.set_status(true, Some(1_000));
+
+        bridge.disconnect(2);
+        assert!(bridge.status().is_some());
+        bridge.disconnect(1);
+        assert_eq!(bridge.status(), None);
+        assert_eq!(
+            bridge.send(BridgeCommand::Play),
+            Err("No browser extension is connected".into())
+        );
+    }
+}
@@ -0,0 +1,118 @@
+use std::collections::VecDeque;
+
+/// Number of recent ping/pong round trips kept for the estimate.
+const WINDOW_SIZE: usize = 8;
+
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+    rtt_ms: i64,
+    offset_ms: i64,
+}
+
+/// NTP-style estimate of how far the server clock is ahead of ours.
+///
+/// Each pong gives one sample: assuming the request and reply legs take
+/// equally long, the server stamped its time halfway through the round
+/// trip. Asymmetric delays skew samples with long round trips the most, so
+/// the estimate uses the lowest-RTT sample in a sliding window.
+#[derive(Debug, Default)]
+pub struct ClockSync {
+    samples: VecDeque<Sample>,
+}
+
+impl ClockSync {
+    pub fn add_sample(&mut self, sent_at: i64, server_time: i64, received_at: i64) {
+        let rtt_ms = received_at - sent_at;
+        if rtt_ms < 0 {
+            return;
+        }
+
+        let offset_ms = server_time - (sent_at + rtt_ms / 2);
+        if self.samples.len() == WINDOW_SIZE {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(Sample { rtt_ms, offset_ms });
+    }
+
+    pub fn offset_ms(&self) -> Option<i64> {
+        self.samples
+            .iter()
+            .min_by_key(|s| s.rtt_ms)
+            .map(|s| s.offset_ms)
+    }
+
+    pub fn min_rtt_ms(&self) -> Option<i64> {
+        self.samples.iter().map(|s| s.rtt_ms).min()
+    }
+
+    /// Converts a server-clock timestamp into our local clock.
+    pub fn to_local(&self, server_time: i64) -> i64 {
+        server_time - self.offset_ms().unwrap_or(0)
+    }
+
+    /// Converts a local timestamp into the server clock.
+    pub fn to_server(&self, local_time: i64) -> i64 {
+        local_time + self.offset_ms().unwrap_or(0)
+    }
+
+    pub fn reset(&mut self) {
+        self.samples.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn offset_is_how_far_the_server_is_ahead() {
+        let mut clock = ClockSync::default();
+        assert_eq!(clock.offset_ms(), None);
+
+        // Sent at 1000, answered at 1050 local; server stamped 5025
+        clock.add_sample(1_000, 5_025, 1_050);
+        assert_eq!(clock.offset_ms(), Some(4_000));
+        assert_eq!(clock.to_server(2_000), 6_000);
+        assert_eq!(clock.to_local(6_000), 2_000);
+
+        // A server behind us gives a negative offset
+        clock.reset();
+        clock.add_sample(1_000, 525, 1_050);
+        assert_eq!(clock.offset_ms(), Some(-500));
+        assert_eq!(clock.to_server(2_000), 1_500);
+    }
+
+    #[test]
+    fn estimate_uses_the_fastest_round_trip() {
+        let mut clock = ClockSync::default();
+        clock.add_sample(0, 1_300, 400);
+        clock.add_sample(1_000, 2_020, 1_040);
+        clock.add_sample(2_000, 3_200, 2_200);
+
+        assert_eq!(clock.min_rtt_ms(), Some(40));
+        assert_eq!(clock.offset_ms(), Some(1_000));
+    }
+
+    #[test]
+    fn old_samples_leave_the_window() {
+        let mut clock = ClockSync::default();
+        // A fast sample, then enough slow ones to push it out
+        clock.add_sample(0, 100, 10);
+        for i in 1..WINDOW_SIZE as i64 {
+            clock.add_sample(i * 1_000, i * 1_000 + 250, i * 1_000 + 100);
+        }
+        assert_eq!(clock.min_rtt_ms(), Some(10));
+        assert_eq!(clock.offset_ms(), Some(95));
+
+        clock.add_sample(9_000, 9_250, 9_100);
+        assert_eq!(clock.min_rtt_ms(), Some(100));
+        assert_eq!(clock.offset_ms(), Some(200));
+    }
+
+    #[test]
+    fn impossible_round_trips_are_ignored() {
+        let mut clock = ClockSync::default();
+        clock.add_sample(1_000, 5_000, 900);
+        assert_eq!(clock.offset_ms(), None);
+    }
+}
@@ -2,9 +2,16 @@ use std::sync::Arc;
 
 use tauri::{AppHandle, State};
 
-use crate::protocol::Message;
+use crate::auth::RoomCredentials;
+use crate::bridge::BridgeStatus;
+use crate::drift::DriftSettings;
+use crate::hooks::HookSettings;
+use crate::identity::Identity;
+use crate::media::{self, MediaAction, MediaSettings, MediaTarget, PlaybackState};
+use crate::outbox::QueuePolicy;
+use crate::protocol::{Capability, Member, Message, RoomSnapshot};
 use crate::state::{AppState, ConnectionStatus};
-use crate::websocket;
+use crate::{schedule, watcher, websocket};
 
 #[tauri::command]
 pub async fn connect(
@@ -12,8 +19,16 @@ pub async fn connect(
     state: State<'_, Arc<AppState>>,
     server_url: String,
     room: String,
+    token: Option<String>,
+    passphrase: Option<String>,
+    encrypt: Option<bool>,
 ) -> Result<(), String> {
-    websocket::connect(app, state.inner().clone(), server_url, room).await
+    let credentials = RoomCredentials {
+        token: token.filter(|t| !t.is_empty()),
+        passphrase: passphrase.filter(|p| !p.is_empty()),
+        encrypt: encrypt.unwrap_or(false),
+    };
+    websocket::connect(app, state.inner().clone(), server_url, room, credentials).await
 }
 
 #[tauri::command]
@@ -26,12 +41,178 @@ pub fn get_connection_status(state: State<'_, Arc<AppState>>) -> ConnectionStatu
     state.get_status()
 }
 
+/// Sends a toggle to the room and toggles the local player at the same
+/// scheduled instant; the server's echo of it is ignored.
 #[tauri::command]
-pub fn send_toggle(state: State<'_, Arc<AppState>>) -> Result<(), String> {
-    state.send_message(Message::toggle())
+pub fn send_toggle(app: AppHandle, state: State<'_, Arc<AppState>>) -> Result<(), String> {
+    let execute_at = schedule::deadline(&state);
+    websocket::send(&app, &state, Message::toggle().with_execute_at(execute_at))?;
+
+    let (media_app, media_state) = (app.clone(), state.inner().clone());
+    schedule::run_at(&state, execute_at, move || {
+        media::simulate_toggle(&media_app, &media_state);
+    });
+
+    let mut msg = Message::toggle();
+    state.stamp(&mut msg);
+    websocket::emit_sync_event(&app, &state, &msg);
+    Ok(())
+}
+
+/// Sends a media key press to the room and presses it locally at the same
+/// scheduled instant.
+#[tauri::command]
+pub fn send_media_action(
+    app: AppHandle,
+    state: State<'_, Arc<AppState>>,
+    action: MediaAction,
+) -> Result<(), String> {
+    let execute_at = schedule::deadline(&state);
+    let msg = Message::new(action.message_kind()).with_execute_at(execute_at);
+    websocket::send(&app, &state, msg)?;
+
+    let (media_app, media_state) = (app.clone(), state.inner().clone());
+    schedule::run_at(&state, execute_at, move || {
+        media::simulate(&media_app, &media_state, action);
+    });
+
+    let mut msg = Message::new(action.message_kind());
+    state.stamp(&mut msg);
+    websocket::emit_sync_event(&app, &state, &msg);
+    Ok(())
 }
 
 #[tauri::command]
 pub fn send_ping(state: State<'_, Arc<AppState>>) -> Result<(), String> {
     state.send_message(Message::ping())
 }
+
+#[tauri::command]
+pub fn send_seek(
+    app: AppHandle,
+    state: State<'_, Arc<AppState>>,
+    position_ms: i64,
+) -> Result<(), String> {
+    if !state.supports(Capability::Seek) {
+        return Err("Server does not support seek".into());
+    }
+    let execute_at = schedule::deadline(&state);
+    websocket::send(
+        &app,
+        &state,
+        Message::seek(position_ms).with_execute_at(execute_at),
+    )?;
+
+    let (media_app, media_state) = (app.clone(), state.inner().clone());
+    schedule::run_at(&state, execute_at, move || {
+        media::seek(&media_app, &media_state, position_ms);
+    });
+
+    let mut msg = Message::seek(position_ms);
+    state.stamp(&mut msg);
+    websocket::emit_sync_event(&app, &state, &msg);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn set_queue_policy(state: State<'_, Arc<AppState>>, msg_type: String, policy: QueuePolicy) {
+    state.set_queue_policy(msg_type, policy);
+}
+
+#[tauri::command]
+pub fn get_room_state(state: State<'_, Arc<AppState>>) -> Option<RoomSnapshot> {
+    state.get_room_state()
+}
+
+#[tauri::command]
+pub fn set_align_on_join(state: State<'_, Arc<AppState>>, enabled: bool) {
+    state.set_align_on_join(enabled);
+}
+
+#[tauri::command]
+pub fn get_drift_settings(state: State<'_, Arc<AppState>>) -> DriftSettings {
+    state.drift_settings()
+}
+
+#[tauri::command]
+pub fn set_drift_settings(state: State<'_, Arc<AppState>>, settings: DriftSettings) {
+    state.set_drift_settings(settings);
+}
+
+#[tauri::command]
+pub fn get_hook_settings(state: State<'_, Arc<AppState>>) -> HookSettings {
+    state.hook_settings()
+}
+
+#[tauri::command]
+pub fn set_hook_settings(state: State<'_, Arc<AppState>>, settings: HookSettings) {
+    state.set_hook_settings(settings);
+}
+
+#[tauri::command]
+pub fn get_members(state: State<'_, Arc<AppState>>) -> Vec<Member> {
+    state.get_members()
+}
+
+#[tauri::command]
+pub fn get_identity(state: State<'_, Arc<AppState>>) -> Option<Identity> {
+    state.get_identity()
+}
+
+#[tauri::command]
+pub fn set_display_name(
+    state: State<'_, Arc<AppState>>,
+    display_name: Option<String>,
+) -> Result<(), String> {
+    state.set_display_name(display_name)
+}
+
+#[tauri::command]
+pub fn list_media_players() -> Result<Vec<String>, String> {
+    media::list_players()
+}
+
+#[tauri::command]
+pub fn get_media_settings(state: State<'_, Arc<AppState>>) -> MediaSettings {
+    state.media_settings()
+}
+
+#[tauri::command]
+pub fn set_media_settings(
+    app: AppHandle,
+    state: State<'_, Arc<AppState>>,
+    settings: MediaSettings,
+) -> Result<(), String> {
+    state.set_media_settings(settings)?;
+    watcher::restart(&app, &state);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_media_targets(state: State<'_, Arc<AppState>>) -> Vec<MediaTarget> {
+    state.media_targets()
+}
+
+#[tauri::command]
+pub fn set_media_targets(
+    app: AppHandle,
+    state: State<'_, Arc<AppState>>,
+    targets: Vec<MediaTarget>,
+) -> Result<(), String> {
+    state.set_media_targets(targets)?;
+    watcher::restart(&app, &state);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_playback_state(state: State<'_, Arc<AppState>>) -> Result<PlaybackState, String> {
+    state
+        .primary_media()
+        .ok_or("No media backend is enabled")?
+        .query()
+}
+
+#[tauri::command]
+pub fn get_bridge_status(state: State<'_, Arc<AppState>>) -> BridgeStatus {
+    state.bridge.summary()
+}
@@ -0,0 +1,215 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use serde_json::{Map, Value};
+use sha2::Sha256;
+
+/// `type` of a message whose body is encrypted.
+pub const ENCRYPTED_TYPE: &str = "encrypted";
+
+/// Fields left in the clear so the server can route and acknowledge.
+const ROUTING_FIELDS: &[&str] = &["clientId", "seq"];
+
+const KEY_ITERATIONS: u32 = 100_000;
+const NONCE_LEN: usize = 12;
+
+/// Symmetric key shared by everyone who knows the room passphrase.
+#[derive(Clone)]
+pub struct RoomKey {
+    cipher: ChaCha20Poly1305,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum DecryptError {
+    /// End-to-end mode is off, so we hold no key for this room.
+    NoKey,
+    /// The envelope is missing its nonce or ciphertext.
+    Malformed,
+    /// Authentication failed: wrong passphrase or tampered message.
+    WrongKey,
+}
+
+impl std::fmt::Display for DecryptError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NoKey => write!(f, "message is encrypted but no room key is set"),
+            Self::Malformed => write!(f, "encrypted message is malformed"),
+            Self::WrongKey => write!(f, "room key does not match the sender's"),
+        }
+    }
+}
+
+impl RoomKey {
+    /// Stretches the passphrase with PBKDF2, salted by room so the same
+    /// passphrase yields different keys in different rooms.
+    pub fn derive(passphrase: &str, room: &str) -> Self {
+        let key = stretch(passphrase, &format!("dragonfox-mediasync/{}", room));
+        Self {
+            cipher: ChaCha20Poly1305::new(Key::from_slice(&key)),
+        }
+    }
+
+    /// Replaces everything but the routing fields with an `encrypted`
+    /// envelope. The routing fields are bound in as associated data.
+    pub fn seal(&self, message: &mut Value) -> Result<(), String> {
+        let Value::Object(map) = message else {
+            return Err("message is not an object".into());
+        };
+        let mut envelope = take_routing(map);
+        let plaintext = serde_json::to_vec(map).map_err(|e| e.to_string())?;
+
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: &plaintext,
+                    aad: associated_data(&envelope).as_bytes(),
+                },
+            )
+            .map_err(|e| e.to_string())?;
+
+        envelope.insert("type".into(), ENCRYPTED_TYPE.into());
+        envelope.insert("nonce".into(), STANDARD.encode(nonce).into());
+        envelope.insert("ciphertext".into(), STANDARD.encode(ciphertext).into());
+        *map = envelope;
+        Ok(())
+    }
+
+    /// Reverses [`seal`](Self::seal), leaving the original message in place.
+    pub fn open(&self, message: &mut Value) -> Result<(), DecryptError> {
+        let Value::Object(map) = message else {
+            return Err(DecryptError::Malformed);
+        };
+        let nonce = decode_field(map, "nonce")?;
+        let ciphertext = decode_field(map, "ciphertext")?;
+        if nonce.len() != NONCE_LEN {
+            return Err(DecryptError::Malformed);
+        }
+
+        let routing = take_routing(map);
+        let plaintext = self
+            .cipher
+            .decrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: &ciphertext,
+                    aad: associated_data(&routing).as_bytes(),
+                },
+            )
+            .map_err(|_| DecryptError::WrongKey)?;
+
+        let Ok(Value::Object(mut body)) = serde_json::from_slice(&plaintext) else {
+            return Err(DecryptError::Malformed);
+        };
+        body.extend(routing);
+        *map = body;
+        Ok(())
+    }
+}
+
+/// A 256-bit key stretched from the passphrase with PBKDF2, so anything
+/// keyed with it is as slow to brute-force as the passphrase allows.
+pub fn stretch(passphrase: &str, salt: &str) -> [u8; 32] {
+    let mut key = [0u8; 32];
+    pbkdf2::pbkdf2_hmac::<Sha256>(
+        passphrase.as_bytes(),
+        salt.as_bytes(),
+        KEY_ITERATIONS,
+        &mut key,
+    );
+    key
+}
+
+/// Whether `message` is an encrypted envelope.
+pub fn is_sealed(message: &Value) -> bool {
+    message.get("type").and_then(Value::as_str) == Some(ENCRYPTED_TYPE)
+}
+
+fn take_routing(map: &mut Map<String, Value>) -> Map<String, Value> {
+    ROUTING_FIELDS
+        .iter()
+        .filter_map(|&field| map.remove(field).map(|v| (field.to_string(), v)))
+        .collect()
+}
+
+fn associated_data(routing: &Map<String, Value>) -> String {
+    ROUTING_FIELDS
+        .iter()
+        .map(|&field| routing.get(field).map(Value::to_string).unwrap_or_default())
+        .collect::<Vec<_>>()
+        .join("|")
+}
+
+fn decode_field(map: &Map<String, Value>, field: &str) -> Result<Vec<u8>, DecryptError> {
+    map.get(field)
+        .and_then(Value::as_str)
+        .and_then(|s| STANDARD.decode(s).ok())
+        .ok_or(DecryptError::Malformed)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn sealed(key: &RoomKey) -> Value {
+        let mut message = json!({"type": "seek", "position": 5, "clientId": "a", "seq": 1});
+        key.seal(&mut message).unwrap();
+        message
+    }
+
+    #[test]
+    fn sealed_messages_open() {
+        let key = RoomKey::derive("secret", "room");
+        let mut message = sealed(&key);
+
+        assert!(is_sealed(&message));
+        assert_eq!(message.get("position"), None);
+        assert_eq!(message["clientId"], "a");
+
+        key.open(&mut message).unwrap();
+        assert_eq!(
+            message,
+            json!({"type": "seek", "position": 5, "clientId": "a", "seq": 1})
+        );
+    }
+
+    #[test]
+    fn keys_differ_by_passphrase_and_room() {
+        let mut message = sealed(&RoomKey::derive("secret", "room"));
+
+        for key in [
+            RoomKey::derive("guess", "room"),
+            RoomKey::derive("secret", "other"),
+        ] {
+            assert_eq!(key.open(&mut message.clone()), Err(DecryptError::WrongKey));
+        }
+        assert!(RoomKey::derive("secret", "room").open(&mut message).is_ok());
+    }
+
+    #[test]
+    fn routing_fields_are_authenticated() {
+        let key = RoomKey::derive("secret", "room");
+        let mut message = sealed(&key);
+
+        message["seq"] = json!(2);
+        assert_eq!(key.open(&mut message), Err(DecryptError::WrongKey));
+    }
+
+    #[test]
+    fn malformed_envelopes_are_reported() {
+        let key = RoomKey::derive("secret", "room");
+        let mut message = sealed(&key);
+        message["nonce"] = json!("c2hvcnQ=");
+
+        assert_eq!(key.open(&mut message), Err(DecryptError::Malformed));
+        assert_eq!(
+            key.open(&mut json!({"type": ENCRYPTED_TYPE})),
+            Err(DecryptError::Malformed)
+        );
+    }
+}
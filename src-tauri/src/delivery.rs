@@ -0,0 +1,146 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use crate::protocol::{self, Message};
+
+/// Unacked control messages kept for retransmission. Oldest are dropped
+/// beyond this so a server that never acks can't grow it forever.
+const MAX_UNACKED: usize = 64;
+/// Sequence numbers remembered per remote client for duplicate detection.
+const DEDUPE_WINDOW: usize = 256;
+
+/// Numbers our outgoing control messages and holds them until the server
+/// acknowledges them.
+pub struct Outbound {
+    next_seq: u64,
+    unacked: BTreeMap<u64, Message>,
+}
+
+impl Default for Outbound {
+    fn default() -> Self {
+        Self {
+            // Start from the launch time so sequence numbers keep increasing
+            // across restarts and peers don't mistake them for duplicates.
+            next_seq: protocol::now_ms() as u64,
+            unacked: BTreeMap::new(),
+        }
+    }
+}
+
+impl Outbound {
+    /// Assigns the next sequence number to `msg` and tracks it until acked.
+    pub fn track(&mut self, msg: &mut Message) {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        msg.seq = Some(seq);
+
+        self.unacked.insert(seq, msg.clone());
+        if self.unacked.len() > MAX_UNACKED {
+            self.unacked.pop_first();
+        }
+    }
+
+    pub fn ack(&mut self, seq: u64) -> bool {
+        self.unacked.remove(&seq).is_some()
+    }
+
+    /// Unacked messages in the order they were originally sent.
+    pub fn unacked(&self) -> Vec<Message> {
+        self.unacked.values().cloned().collect()
+    }
+
+    pub fn clear(&mut self) {
+        self.unacked.clear();
+    }
+}
+
+/// Remembers which `(clientId, seq)` pairs have already been applied so a
+/// re-delivered control message is ignored.
+#[derive(Default)]
+pub struct SeenSeqs {
+    by_client: HashMap<String, BTreeSet<u64>>,
+}
+
+impl SeenSeqs {
+    /// Records the pair; returns `false` if it was seen before.
+    pub fn insert(&mut self, client_id: &str, seq: u64) -> bool {
+        let seen = self.by_client.entry(client_id.to_string()).or_default();
+
+        // Older than anything in a full window: assume it was applied long ago
+        if seen.len() >= DEDUPE_WINDOW && seen.first().is_some_and(|&first| seq < first) {
+            return false;
+        }
+        if !seen.insert(seq) {
+            return false;
+        }
+        if seen.len() > DEDUPE_WINDOW {
+            seen.pop_first();
+        }
+        true
+    }
+
+    pub fn clear(&mut self) {
+        self.by_client.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::MessageKind;
+
+    #[test]
+    fn tracked_messages_wait_for_their_ack() {
+        let mut outbound = Outbound::default();
+        let mut play = Message::new(MessageKind::Play);
+        let mut pause = Message::new(MessageKind::Pause);
+        outbound.track(&mut play);
+        outbound.track(&mut pause);
+
+        let (play_seq, pause_seq) = (play.seq.unwrap(), pause.seq.unwrap());
+        assert_eq!(pause_seq, play_seq + 1);
+
+        assert!(outbound.ack(play_seq));
+        assert!(!outbound.ack(play_seq));
+        let pending: Vec<_> = outbound.unacked().iter().map(|m| m.seq).collect();
+        assert_eq!(pending, [Some(pause_seq)]);
+    }
+
+    #[test]
+    fn oldest_unacked_are_dropped() {
+        let mut outbound = Outbound::default();
+        let seqs: Vec<u64> = (0..MAX_UNACKED + 2)
+            .map(|_| {
+                let mut msg = Message::toggle();
+                outbound.track(&mut msg);
+                msg.seq.unwrap()
+            })
+            .collect();
+
+        let pending: Vec<u64> = outbound.unacked().iter().filter_map(|m| m.seq).collect();
+        assert_eq!(pending, seqs[2..]);
+    }
+
+    #[test]
+    fn repeats_are_seen_per_client() {
+        let mut seen = SeenSeqs::default();
+        assert!(seen.insert("a", 7));
+        assert!(!seen.insert("a", 7));
+        assert!(seen.insert("b", 7));
+        // Late arrivals inside the window are still new
+        assert!(seen.insert("a", 5));
+    }
+
+    #[test]
+    fn anything_older_than_a_full_window_is_a_repeat() {
+        let mut seen = SeenSeqs::default();
+        let window = DEDUPE_WINDOW as u64;
+        for seq in 100..100 + window + 1 {
+            assert!(seen.insert("a", seq));
+        }
+
+        // 100 fell out of the window, and so did everything before it
+        assert!(!seen.insert("a", 100));
+        assert!(!seen.insert("a", 50));
+        assert!(seen.insert("a", 100 + window + 1));
+    }
+}
@@ -0,0 +1,220 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter};
+use tokio::task::JoinHandle;
+use tracing::{debug, info};
+
+use crate::media;
+use crate::protocol::{self, Capability, Message, MessageKind};
+use crate::state::{AppState, ConnectionStatus};
+
+/// Floor for the check interval, so a bad setting can't flood the room.
+const MIN_INTERVAL_MS: u64 = 1_000;
+
+/// Reports older than this many intervals are from clients that have
+/// stopped reporting, e.g. because they left or paused.
+const STALE_INTERVALS: i64 = 3;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct DriftSettings {
+    /// Off until the user opts in, since it seeks their player on its own
+    /// and reports their position to the room.
+    pub enabled: bool,
+    /// Seek once we're further than this from the reference position.
+    pub threshold_ms: i64,
+    /// How often to report our position and check for drift.
+    pub interval_ms: u64,
+    /// Member whose position to follow, e.g. whoever is hosting. `None`
+    /// follows the median of the room.
+    pub follow: Option<String>,
+}
+
+impl Default for DriftSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            threshold_ms: 2_000,
+            interval_ms: 5_000,
+            follow: None,
+        }
+    }
+}
+
+/// Last position a peer reported, on our clock.
+#[derive(Debug, Clone, Copy)]
+pub struct PeerPosition {
+    pub position_ms: i64,
+    pub playing: bool,
+    pub reported_at: i64,
+}
+
+impl PeerPosition {
+    /// Where the peer's player should be by `now`.
+    fn at(&self, now: i64) -> i64 {
+        if self.playing {
+            self.position_ms + (now - self.reported_at).max(0)
+        } else {
+            self.position_ms
+        }
+    }
+
+    /// Whether the peer is still reporting, given how often reports come.
+    fn is_fresh(&self, now: i64, interval_ms: i64) -> bool {
+        now - self.reported_at < interval_ms * STALE_INTERVALS
+    }
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct DriftCorrected {
+    /// How far ahead (positive) or behind (negative) of the room we were.
+    drift_ms: i64,
+    from_ms: i64,
+    to_ms: i64,
+    /// Member we followed, or `None` for the room median.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reference: Option<String>,
+    peers: usize,
+}
+
+/// Periodically reports our position to the room and seeks back in line
+/// when we've drifted. Runs for the life of one connection.
+pub fn spawn(app: AppHandle, state: Arc<AppState>) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        loop {
+            let interval_ms = state.drift_settings().interval_ms.max(MIN_INTERVAL_MS);
+            tokio::time::sleep(Duration::from_millis(interval_ms)).await;
+
+            // Backends talk to players synchronously
+            let (app, state) = (app.clone(), state.clone());
+            let _ = tokio::task::spawn_blocking(move || check(&app, &state)).await;
+        }
+    })
+}
+
+fn check(app: &AppHandle, state: &AppState) {
+    let settings = state.drift_settings();
+    if !settings.enabled
+        || !state.supports(Capability::Seek)
+        || !matches!(state.get_status(), ConnectionStatus::Connected { .. })
+    {
+        return;
+    }
+
+    let Some(Ok(local)) = state.primary_media().map(|media| media.query()) else {
+        return;
+    };
+    let Some(position) = local.position_ms else {
+        return;
+    };
+    let report = Message::new(MessageKind::Position {
+        position,
+        playing: local.playing,
+    });
+    if let Err(e) = state.send_message(report) {
+        debug!("Failed to report position: {}", e);
+    }
+
+    // Only running players drift, and a seek we just made needs a round
+    // of fresh reports before it can be judged
+    let interval_ms = settings.interval_ms.max(MIN_INTERVAL_MS) as i64;
+    if !local.playing || state.since_media_command() < interval_ms {
+        return;
+    }
+
+    let now = protocol::now_ms();
+    let peers: Vec<(String, i64)> = state
+        .peer_positions()
+        .into_iter()
+        .filter(|(_, peer)| peer.playing && peer.is_fresh(now, interval_ms))
+        .map(|(client_id, peer)| (client_id, peer.at(now)))
+        .collect();
+
+    let target = match &settings.follow {
+        Some(leader) if Some(leader) == state.client_id().as_ref() => return,
+        Some(leader) => match peers.iter().find(|(client_id, _)| client_id == leader) {
+            Some((_, position)) => *position,
+            None => return,
+        },
+        None if peers.is_empty() => return,
+        None => {
+            let mut positions: Vec<i64> = peers.iter().map(|(_, p)| *p).collect();
+            positions.push(position);
+            median(&mut positions)
+        }
+    };
+
+    let drift_ms = position - target;
+    if drift_ms.abs() <= settings.threshold_ms {
+        return;
+    }
+
+    info!(
+        "Drifted {}ms from the room, seeking {} -> {}",
+        drift_ms, position, target
+    );
+    media::seek(app, state, target);
+    let _ = app.emit(
+        "drift-corrected",
+        DriftCorrected {
+            drift_ms,
+            from_ms: position,
+            to_ms: target,
+            reference: settings.follow,
+            peers: peers.len(),
+        },
+    );
+}
+
+/// Middle value, averaging the middle two for an even count.
+fn median(values: &mut [i64]) -> i64 {
+    values.sort_unstable();
+    let mid = values.len() / 2;
+    if values.len().is_multiple_of(2) {
+        (values[mid - 1] + values[mid]) / 2
+    } else {
+        values[mid]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn peer(position_ms: i64, playing: bool) -> PeerPosition {
+        PeerPosition {
+            position_ms,
+            playing,
+            reported_at: 10_000,
+        }
+    }
+
+    #[test]
+    fn median_of_odd_and_even_counts() {
+        assert_eq!(median(&mut [7]), 7);
+        assert_eq!(median(&mut [9, 1, 5]), 5);
+        assert_eq!(median(&mut [4, 10, 2, 8]), 6);
+        // Unsorted input with an outlier doesn't pull the result
+        assert_eq!(median(&mut [1_000, 3_000, 900_000, 2_000, 2_500]), 2_500);
+    }
+
+    #[test]
+    fn playing_peers_advance_with_time() {
+        assert_eq!(peer(5_000, true).at(12_500), 7_500);
+        assert_eq!(peer(5_000, false).at(12_500), 5_000);
+        // A report from our future (clock skew) doesn't move them back
+        assert_eq!(peer(5_000, true).at(9_000), 5_000);
+    }
+
+    #[test]
+    fn reports_go_stale_after_a_few_intervals() {
+        let p = peer(0, true);
+        assert!(p.is_fresh(10_000, 1_000));
+        assert!(p.is_fresh(12_999, 1_000));
+        assert!(!p.is_fresh(13_000, 1_000));
+        assert!(!p.is_fresh(20_000, 1_000));
+    }
+}
@@ -0,0 +1,179 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Runtime};
+use tracing::info;
+
+use crate::media::{self, MediaAction};
+use crate::protocol::{self, Message, MessageKind};
+use crate::state::AppState;
+
+/// Remote toggles arriving this soon after the first one in a burst are
+/// folded into a single net action. Matches the media cooldown so the net
+/// action lands as soon as we're allowed to press the key again.
+const COALESCE_WINDOW_MS: i64 = 500;
+
+/// A burst of remote toggles currently being coalesced.
+pub struct Burst {
+    until: i64,
+    extra: u32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SuppressReason {
+    /// Our own message relayed back by the server.
+    OwnEcho,
+    /// We pressed the media key too recently.
+    Cooldown,
+    /// Part of a burst of toggles that cancelled out or was merged.
+    Coalesced,
+    /// Unsigned or signed with the wrong room passphrase.
+    Unauthenticated,
+    /// The player already reports the state a play/pause asked for.
+    AlreadyApplied,
+    /// No enabled backend reacts to this message type.
+    NoBackend,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SuppressedEvent {
+    #[serde(rename = "type")]
+    msg_type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    client_id: Option<String>,
+    reason: SuppressReason,
+    detail: String,
+}
+
+/// Logs and emits `sync-suppressed` so the UI can show what was skipped.
+pub fn report<R: Runtime>(
+    app: &AppHandle<R>,
+    msg: &Message,
+    reason: SuppressReason,
+    detail: String,
+) {
+    info!(
+        "Suppressed {} ({:?}): {}",
+        msg.kind.type_name(),
+        reason,
+        detail
+    );
+    let _ = app.emit(
+        "sync-suppressed",
+        SuppressedEvent {
+            msg_type: msg.kind.type_name().to_string(),
+            client_id: msg.client_id.clone(),
+            reason,
+            detail,
+        },
+    );
+}
+
+/// Presses the media key for a remote playback command unless we're
+/// still inside the cooldown from the last press. Play and pause are
+/// skipped by backends that report the player is already in that state,
+/// which keeps them safe without the cooldown, as long as every backend
+/// involved can report it; key simulation can only toggle blindly.
+pub fn apply<R: Runtime>(app: &AppHandle<R>, state: &AppState, msg: &Message) {
+    let Some(action) = MediaAction::from_kind(&msg.kind) else {
+        return;
+    };
+    let backends = state.media_for(msg.kind.type_name());
+    if backends.is_empty() {
+        report(
+            app,
+            msg,
+            SuppressReason::NoBackend,
+            format!("no enabled backend reacts to {}", msg.kind.type_name()),
+        );
+        return;
+    }
+    let idempotent = matches!(msg.kind, MessageKind::Play | MessageKind::Pause)
+        && backends.iter().all(|media| media.query().is_ok());
+    let remaining = state.cooldown_remaining();
+    if remaining > 0 && !idempotent {
+        report(
+            app,
+            msg,
+            SuppressReason::Cooldown,
+            format!("media key pressed {}ms ago", media::COOLDOWN_MS - remaining),
+        );
+        return;
+    }
+    match msg.kind {
+        MessageKind::Play | MessageKind::Pause => {
+            let playing = msg.kind == MessageKind::Play;
+            let acted = if playing {
+                media::play(app, state)
+            } else {
+                media::pause(app, state)
+            };
+            if acted == 0 {
+                report(
+                    app,
+                    msg,
+                    SuppressReason::AlreadyApplied,
+                    format!(
+                        "every player is already {}",
+                        if playing { "playing" } else { "paused" }
+                    ),
+                );
+            }
+        }
+        _ => media::simulate(app, state, action),
+    }
+}
+
+/// Applies a remote toggle, coalescing bursts: the first toggle acts right
+/// away and any that follow within the window are counted, then resolved
+/// into one extra press if their number is odd.
+pub fn apply_toggle<R: Runtime>(app: &AppHandle<R>, state: &Arc<AppState>, msg: &Message) {
+    let now = protocol::now_ms();
+    {
+        let mut burst = state.toggle_burst.write();
+        if let Some(burst) = burst.as_mut()
+            && now < burst.until
+        {
+            burst.extra += 1;
+            return;
+        }
+        *burst = Some(Burst {
+            until: now + COALESCE_WINDOW_MS,
+            extra: 0,
+        });
+    }
+
+    apply(app, state, msg);
+
+    let app = app.clone();
+    let state = state.clone();
+    let msg = msg.clone();
+    std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(COALESCE_WINDOW_MS as u64));
+        let Some(burst) = state.toggle_burst.write().take() else {
+            return;
+        };
+
+        match burst.extra {
+            0 => {}
+            n if n % 2 == 0 => report(
+                &app,
+                &msg,
+                SuppressReason::Coalesced,
+                format!("{} follow-up toggles cancelled out", n),
+            ),
+            n => {
+                report(
+                    &app,
+                    &msg,
+                    SuppressReason::Coalesced,
+                    format!("{} follow-up toggles merged into one", n),
+                );
+                media::simulate_toggle(&app, &state);
+            }
+        }
+    });
+}
@@ -0,0 +1,227 @@
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Runtime};
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tracing::{debug, info, warn};
+
+use crate::protocol::{Message, MessageKind};
+use crate::state::AppState;
+
+/// Most of a hook's stderr we keep for the event log.
+const MAX_STDERR_CHARS: usize = 1_000;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct HookSettings {
+    pub hooks: Vec<Hook>,
+    /// Hooks still running after this long are killed.
+    pub timeout_ms: u64,
+    /// Hooks that may run at once. Events arriving while every slot is
+    /// taken skip their hooks rather than pile up behind a slow one.
+    pub max_concurrent: usize,
+}
+
+impl Default for HookSettings {
+    fn default() -> Self {
+        Self {
+            hooks: Vec::new(),
+            timeout_ms: 5_000,
+            max_concurrent: 4,
+        }
+    }
+}
+
+/// An executable run for matching sync events.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Hook {
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// Message types (`play`, `pause`, `memberJoined`, ...) to run for.
+    /// Empty means every playback command.
+    #[serde(default)]
+    pub types: Vec<String>,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+impl Hook {
+    fn matches(&self, msg: &Message) -> bool {
+        if self.types.is_empty() {
+            msg.kind.is_control()
+        } else {
+            self.types.iter().any(|t| t == msg.kind.type_name())
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    In,
+    Out,
+}
+
+impl Direction {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::In => "in",
+            Self::Out => "out",
+        }
+    }
+}
+
+/// What the hook sees on stdin.
+#[derive(Serialize)]
+struct HookEvent<'a> {
+    #[serde(flatten)]
+    msg: &'a Message,
+    direction: Direction,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    room: Option<String>,
+}
+
+/// How a hook run ended, for the event log.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct HookResult {
+    command: String,
+    #[serde(rename = "type")]
+    msg_type: String,
+    direction: Direction,
+    /// `None` when the hook never exited on its own.
+    exit_code: Option<i32>,
+    stderr: String,
+    /// Why the hook didn't run to completion, if it didn't.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Runs every enabled hook that matches `msg` in the background. Hooks see
+/// timestamps on our clock whichever way the message went.
+pub fn run<R: Runtime>(app: &AppHandle<R>, state: &AppState, msg: &Message, direction: Direction) {
+    let settings = state.hook_settings();
+    let hooks: Vec<Hook> = settings
+        .hooks
+        .into_iter()
+        .filter(|hook| hook.enabled && !hook.command.is_empty() && hook.matches(msg))
+        .collect();
+    if hooks.is_empty() {
+        return;
+    }
+
+    // Outgoing messages are stamped in server time by the time they're sent
+    let mut msg = msg.clone();
+    if let Direction::Out = direction {
+        msg.timestamp = state.to_local_time(msg.timestamp);
+    }
+    let msg = &msg;
+
+    let event = HookEvent {
+        msg,
+        direction,
+        room: state.get_room(),
+    };
+    let input = match serde_json::to_vec(&event) {
+        Ok(input) => Arc::new(input),
+        Err(e) => {
+            warn!("Failed to serialize hook event: {}", e);
+            return;
+        }
+    };
+    let timeout = Duration::from_millis(settings.timeout_ms);
+
+    for hook in hooks {
+        let mut result = HookResult {
+            command: hook.command.clone(),
+            msg_type: msg.kind.type_name().to_string(),
+            direction,
+            exit_code: None,
+            stderr: String::new(),
+            error: None,
+        };
+        let Ok(permit) = state.hook_slots().try_acquire_owned() else {
+            result.error = Some("skipped, too many hooks running".into());
+            emit_result(app, result);
+            continue;
+        };
+
+        let mut command = Command::new(&hook.command);
+        command
+            .args(&hook.args)
+            .env("MEDIASYNC_EVENT", msg.kind.type_name())
+            .env("MEDIASYNC_DIRECTION", direction.as_str())
+            .env("MEDIASYNC_TIMESTAMP", msg.timestamp.to_string());
+        if let Some(room) = &event.room {
+            command.env("MEDIASYNC_ROOM", room);
+        }
+        if let Some(client_id) = &msg.client_id {
+            command.env("MEDIASYNC_CLIENT_ID", client_id);
+        }
+        if let MessageKind::Seek { position } = &msg.kind {
+            command.env("MEDIASYNC_POSITION", position.to_string());
+        }
+
+        let (app, input) = (app.clone(), input.clone());
+        tauri::async_runtime::spawn(async move {
+            match tokio::time::timeout(timeout, execute(command, &input)).await {
+                Ok(Ok((code, stderr))) => {
+                    result.exit_code = code;
+                    result.stderr = stderr;
+                }
+                Ok(Err(e)) => result.error = Some(e),
+                Err(_) => result.error = Some(format!("killed after {}ms", timeout.as_millis())),
+            }
+            drop(permit);
+            emit_result(&app, result);
+        });
+    }
+}
+
+/// Spawns the hook, feeds it `input` and waits for it to exit. Dropping the
+/// future kills the process.
+async fn execute(mut command: Command, input: &[u8]) -> Result<(Option<i32>, String), String> {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| format!("cannot start: {}", e))?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        // A hook that doesn't read stdin closes it early; that's fine
+        if let Err(e) = stdin.write_all(input).await {
+            debug!("Hook did not take its input: {}", e);
+        }
+    }
+
+    let output = child.wait_with_output().await.map_err(|e| e.to_string())?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let stderr = match stderr.char_indices().nth(MAX_STDERR_CHARS) {
+        Some((end, _)) => format!("{}…", &stderr[..end]),
+        None => stderr.into_owned(),
+    };
+    Ok((output.status.code(), stderr.trim().to_string()))
+}
+
+fn emit_result<R: Runtime>(app: &AppHandle<R>, result: HookResult) {
+    match (&result.error, result.exit_code) {
+        (Some(e), _) => warn!("Hook {} for {}: {}", result.command, result.msg_type, e),
+        (None, Some(0)) => debug!("Hook {} for {} succeeded", result.command, result.msg_type),
+        (None, code) => info!(
+            "Hook {} for {} exited with {:?}",
+            result.command, result.msg_type, code
+        ),
+    }
+    let _ = app.emit("hook-result", result);
+}
@@ -1,18 +1,17 @@
 use std::sync::Arc;
 
-use tauri::{AppHandle, Emitter};
+use tauri::{AppHandle, Runtime};
 use tauri_plugin_global_shortcut::{GlobalShortcutExt, Shortcut, ShortcutState};
 use tracing::{error, info};
 
-use crate::media;
 use crate::protocol::Message;
 use crate::state::AppState;
+use crate::{media, schedule, websocket};
 
 pub fn register(app: &AppHandle, state: Arc<AppState>, shortcut_str: &str) -> Result<(), String> {
     let shortcut: Shortcut = shortcut_str.parse().map_err(|e| format!("{:?}", e))?;
 
     let app_clone = app.clone();
-    let state_clone = state.clone();
 
     app.global_shortcut()
         .on_shortcut(shortcut, move |_app, _shortcut, event| {
@@ -20,21 +19,33 @@ pub fn register(app: &AppHandle, state: Arc<AppState>, shortcut_str: &str) -> Re
             if event.state != ShortcutState::Pressed {
                 return;
             }
+            on_hotkey(&app_clone, &state);
+        })
+        .map_err(|e| e.to_string())
+}
 
-            info!("Global hotkey triggered");
+/// Toggles playback for the whole room: tells the server, then presses
+/// play/pause locally at the same instant the others will.
+fn on_hotkey<R: Runtime>(app: &AppHandle<R>, state: &Arc<AppState>) {
+    info!("Global hotkey triggered");
 
-            // Send toggle to server
-            if let Err(e) = state_clone.send_message(Message::toggle()) {
-                error!("Failed to send toggle: {}", e);
-            }
+    // Send toggle to server, scheduled so the whole room acts together
+    let execute_at = schedule::deadline(state);
+    let msg = Message::toggle().with_execute_at(execute_at);
+    if let Err(e) = websocket::send(app, state, msg) {
+        error!("Failed to send toggle: {}", e);
+    }
 
-            // Simulate media key locally so player responds
-            media::simulate_toggle(&state_clone);
+    // Simulate media key locally at the same instant so player responds
+    let (media_app, media_state) = (app.clone(), state.clone());
+    schedule::run_at(state, execute_at, move || {
+        media::simulate_toggle(&media_app, &media_state);
+    });
 
-            // Emit to frontend
-            let _ = app_clone.emit("sync-event", Message::toggle());
-        })
-        .map_err(|e| e.to_string())
+    // Emit to frontend
+    let mut msg = Message::toggle();
+    state.stamp(&mut msg);
+    websocket::emit_sync_event(app, state, &msg);
 }
 
 pub fn unregister_all(app: &AppHandle) -> Result<(), String> {
@@ -55,3 +66,62 @@ pub fn set_hotkey(
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use tauri::test::mock_app;
+    use tokio::sync::mpsc::unbounded_channel;
+
+    use super::*;
+    use crate::media::{BackendKind, MediaCall, MediaTarget};
+    use crate::protocol::MessageKind;
+    use crate::state::ConnectionStatus;
+
+    fn setup() -> Arc<AppState> {
+        let state = Arc::new(AppState::new());
+        state
+            .set_media_targets(vec![MediaTarget::new(BackendKind::Recording)])
+            .unwrap();
+        state
+    }
+
+    /// The local toggle runs on a blocking thread; waits up to a second
+    /// for it.
+    fn calls(state: &AppState) -> Vec<MediaCall> {
+        let deadline = Instant::now() + Duration::from_secs(1);
+        while state.recorder.calls().is_empty() && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        state.recorder.calls()
+    }
+
+    #[test]
+    fn hotkey_toggles_the_room_and_the_player() {
+        let (app, state) = (mock_app(), setup());
+        let (tx, mut rx) = unbounded_channel();
+        state.set_sender(Some(tx));
+        state.set_status(ConnectionStatus::Connected {
+            room: "room".into(),
+            latency_ms: None,
+        });
+        state.clock.write().add_sample(1_000, 1_060, 1_020);
+
+        on_hotkey(app.handle(), &state);
+
+        let sent = rx.try_recv().unwrap();
+        assert_eq!(sent.kind, MessageKind::Toggle);
+        assert!(sent.execute_at.is_some());
+        assert_eq!(calls(&state), vec![MediaCall::Toggle]);
+    }
+
+    #[test]
+    fn hotkey_still_toggles_the_player_offline() {
+        let (app, state) = (mock_app(), setup());
+
+        on_hotkey(app.handle(), &state);
+
+        assert_eq!(calls(&state), vec![MediaCall::Toggle]);
+    }
+}
@@ -0,0 +1,55 @@
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+const IDENTITY_FILE: &str = "identity.json";
+
+/// Stable identity for this install, persisted in the app data dir so the
+/// room sees the same client across restarts.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Identity {
+    pub client_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+}
+
+impl Identity {
+    fn generate() -> Self {
+        Self {
+            client_id: uuid::Uuid::new_v4().to_string(),
+            display_name: None,
+        }
+    }
+
+    /// Loads the saved identity from `dir`, creating and saving a new one if
+    /// there is none yet or it can't be read.
+    pub fn load_or_create(dir: &Path) -> Self {
+        let path = dir.join(IDENTITY_FILE);
+        match fs::read_to_string(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|json| serde_json::from_str::<Self>(&json).map_err(|e| e.to_string()))
+        {
+            Ok(identity) => identity,
+            Err(e) => {
+                if path.exists() {
+                    warn!("Failed to read {}: {}", path.display(), e);
+                }
+                let identity = Self::generate();
+                info!("Generated client ID {}", identity.client_id);
+                if let Err(e) = identity.save(dir) {
+                    warn!("Failed to save identity: {}", e);
+                }
+                identity
+            }
+        }
+    }
+
+    pub fn save(&self, dir: &Path) -> Result<(), String> {
+        fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        let json = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        fs::write(dir.join(IDENTITY_FILE), json).map_err(|e| e.to_string())
+    }
+}
@@ -1,14 +1,31 @@
+mod auth;
+mod bridge;
+mod clock;
 mod commands;
+mod crypto;
+mod delivery;
+mod drift;
+mod echo;
+mod hooks;
 mod hotkey;
+mod identity;
 mod media;
+mod outbox;
 mod protocol;
+mod schedule;
 mod state;
 mod tray;
+mod watcher;
 mod websocket;
 
 use std::sync::Arc;
 
+// For the integration tests, which drive backends against stand-in players
+#[cfg(target_os = "linux")]
+pub use media::MprisBackend;
+pub use media::{MediaAction, MediaBackend, PlaybackState, PlayerChange};
 use state::AppState;
+use tauri::Manager;
 use tracing_subscriber::EnvFilter;
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
@@ -26,6 +43,17 @@ pub fn run() {
         .plugin(tauri_plugin_global_shortcut::Builder::new().build())
         .manage(app_state)
         .setup(|app| {
+            // Load or create our persistent client identity
+            let data_dir = app.path().app_data_dir()?;
+            app.state::<Arc<AppState>>().init_identity(data_dir);
+
+            // Listen for the browser extension
+            let state = app.state::<Arc<AppState>>().inner().clone();
+            bridge::start(app.handle().clone(), state.clone());
+
+            // Pick up play/pause/seek the user does in the player itself
+            watcher::restart(app.handle(), &state);
+
             // Create system tray
             tray::create(app.handle())?;
             Ok(())
@@ -35,7 +63,26 @@ pub fn run() {
             commands::disconnect,
             commands::get_connection_status,
             commands::send_toggle,
+            commands::send_media_action,
+            commands::list_media_players,
+            commands::get_media_settings,
+            commands::set_media_settings,
+            commands::get_media_targets,
+            commands::set_media_targets,
+            commands::get_playback_state,
+            commands::get_bridge_status,
             commands::send_ping,
+            commands::send_seek,
+            commands::set_queue_policy,
+            commands::get_room_state,
+            commands::set_align_on_join,
+            commands::get_drift_settings,
+            commands::set_drift_settings,
+            commands::get_hook_settings,
+            commands::set_hook_settings,
+            commands::get_members,
+            commands::get_identity,
+            commands::set_display_name,
             hotkey::set_hotkey,
         ])
         .run(tauri::generate_context!())
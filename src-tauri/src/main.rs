@@ -2,5 +2,5 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 fn main() {
-    media_sync_client_lib::run()
+    dragonfox_mediasync_client_lib::run()
 }
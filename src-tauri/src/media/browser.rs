@@ -0,0 +1,58 @@
+use std::sync::Arc;
+
+use super::{MediaAction, MediaBackend, PlaybackState};
+use crate::bridge::{Bridge, BridgeCommand};
+
+/// Controls the active video in a browser tab through the companion
+/// extension connected to the localhost bridge.
+pub struct BrowserBackend {
+    bridge: Arc<Bridge>,
+}
+
+impl BrowserBackend {
+    pub fn new(bridge: Arc<Bridge>) -> Self {
+        Self { bridge }
+    }
+}
+
+impl MediaBackend for BrowserBackend {
+    fn name(&self) -> &'static str {
+        "browser"
+    }
+
+    fn play(&self) -> Result<(), String> {
+        self.bridge.send(BridgeCommand::Play)
+    }
+
+    fn pause(&self) -> Result<(), String> {
+        self.bridge.send(BridgeCommand::Pause)
+    }
+
+    fn toggle(&self) -> Result<(), String> {
+        self.bridge.send(BridgeCommand::Toggle)
+    }
+
+    fn seek(&self, position_ms: i64) -> Result<(), String> {
+        self.bridge.send(BridgeCommand::Seek {
+            position: position_ms,
+        })
+    }
+
+    fn query(&self) -> Result<PlaybackState, String> {
+        self.bridge
+            .status()
+            .ok_or_else(|| "the browser extension hasn't reported any video yet".into())
+    }
+
+    fn perform(&self, action: MediaAction) -> Result<(), String> {
+        self.bridge.send(match action {
+            MediaAction::PlayPause => BridgeCommand::Toggle,
+            MediaAction::Next => BridgeCommand::Next,
+            MediaAction::Previous => BridgeCommand::Previous,
+            MediaAction::Stop => BridgeCommand::Stop,
+            MediaAction::VolumeUp => BridgeCommand::VolumeUp,
+            MediaAction::VolumeDown => BridgeCommand::VolumeDown,
+            MediaAction::Mute => BridgeCommand::Mute,
+        })
+    }
+}
@@ -0,0 +1,200 @@
+use tracing::{error, info, warn};
+
+use super::{MediaAction, MediaBackend, PlaybackState};
+
+/// Presses OS media keys. Whichever app the OS routes them to responds, and
+/// there's no way to address play or pause directly or to read state back.
+/// On Linux the keys come from a uinput virtual keyboard.
+pub struct KeyBackend;
+
+impl MediaBackend for KeyBackend {
+    fn name(&self) -> &'static str {
+        "keys"
+    }
+
+    fn play(&self) -> Result<(), String> {
+        self.toggle()
+    }
+
+    fn pause(&self) -> Result<(), String> {
+        self.toggle()
+    }
+
+    fn toggle(&self) -> Result<(), String> {
+        self.perform(MediaAction::PlayPause)
+    }
+
+    fn seek(&self, position_ms: i64) -> Result<(), String> {
+        Err(format!("cannot seek to {}ms with media keys", position_ms))
+    }
+
+    fn query(&self) -> Result<PlaybackState, String> {
+        Err("media keys cannot report playback state".into())
+    }
+
+    fn perform(&self, action: MediaAction) -> Result<(), String> {
+        #[cfg(target_os = "linux")]
+        return super::uinput::press(action);
+
+        #[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+        return Err(format!(
+            "no media key support for {:?} on this platform",
+            action
+        ));
+
+        #[cfg(any(target_os = "macos", target_os = "windows"))]
+        {
+            std::thread::spawn(move || {
+                std::thread::sleep(std::time::Duration::from_millis(50));
+
+                #[cfg(target_os = "macos")]
+                match nx_keytype(action) {
+                    Some(key_code) => simulate_macos(key_code, action),
+                    None => warn!("{:?} has no media key on macOS", action),
+                }
+
+                #[cfg(target_os = "windows")]
+                simulate_windows(virtual_key(action), action);
+            });
+            Ok(())
+        }
+    }
+}
+
+/// `NX_KEYTYPE_*` code. macOS has no stop key.
+#[cfg(target_os = "macos")]
+fn nx_keytype(action: MediaAction) -> Option<isize> {
+    match action {
+        MediaAction::PlayPause => Some(16),
+        MediaAction::Next => Some(17),
+        MediaAction::Previous => Some(18),
+        MediaAction::Stop => None,
+        MediaAction::VolumeUp => Some(0),
+        MediaAction::VolumeDown => Some(1),
+        MediaAction::Mute => Some(7),
+    }
+}
+
+/// `VK_MEDIA_*` / `VK_VOLUME_*` virtual key code.
+#[cfg(target_os = "windows")]
+fn virtual_key(action: MediaAction) -> u16 {
+    match action {
+        MediaAction::PlayPause => 0xB3,
+        MediaAction::Next => 0xB0,
+        MediaAction::Previous => 0xB1,
+        MediaAction::Stop => 0xB2,
+        MediaAction::VolumeUp => 0xAF,
+        MediaAction::VolumeDown => 0xAE,
+        MediaAction::Mute => 0xAD,
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn simulate_macos(key_code: isize, action: MediaAction) {
+    use objc2_app_kit::{NSEvent, NSEventModifierFlags, NSEventType};
+    use objc2_core_graphics::{CGEvent, CGEventTapLocation};
+    use objc2_foundation::NSPoint;
+
+    fn post_media_key(key_code: isize, key_down: bool) {
+        let flags = NSEventModifierFlags::from_bits_retain(if key_down { 0xa00 } else { 0xb00 });
+        let data1: isize = (key_code << 16) | ((if key_down { 0xa } else { 0xb }) << 8);
+
+        let event = NSEvent::otherEventWithType_location_modifierFlags_timestamp_windowNumber_context_subtype_data1_data2(
+            NSEventType::SystemDefined,
+            NSPoint::new(0.0, 0.0),
+            flags,
+            0.0,
+            0,
+            None,
+            8, // NX_SUBTYPE_AUX_CONTROL_BUTTONS
+            data1,
+            -1,
+        );
+
+        if let Some(ev) = event {
+            if let Some(cg_event) = ev.CGEvent() {
+                CGEvent::post(CGEventTapLocation(0), Some(&cg_event));
+                info!("Posted media key event");
+            } else {
+                error!("Failed to get CGEvent from NSEvent");
+            }
+        } else {
+            error!("Failed to create NSEvent");
+        }
+    }
+
+    info!("Posting media key down...");
+    post_media_key(key_code, true);
+    std::thread::sleep(std::time::Duration::from_millis(100));
+    info!("Posting media key up...");
+    post_media_key(key_code, false);
+    info!("Simulated media {:?} (macOS)", action);
+}
+
+#[cfg(target_os = "windows")]
+fn simulate_windows(virtual_key: u16, action: MediaAction) {
+    use std::ptr::null_mut;
+
+    const KEYEVENTF_EXTENDEDKEY: u32 = 0x0001;
+    const KEYEVENTF_KEYUP: u32 = 0x0002;
+    const INPUT_KEYBOARD: u32 = 1;
+
+    #[repr(C)]
+    struct KeyboardInput {
+        r#type: u32,
+        ki: KeybdInput,
+    }
+
+    #[repr(C)]
+    struct KeybdInput {
+        wVk: u16,
+        wScan: u16,
+        dwFlags: u32,
+        time: u32,
+        dwExtraInfo: usize,
+        _padding: [u8; 8],
+    }
+
+    #[link(name = "user32")]
+    extern "system" {
+        fn SendInput(cInputs: u32, pInputs: *const KeyboardInput, cbSize: i32) -> u32;
+    }
+
+    let mut inputs = [
+        KeyboardInput {
+            r#type: INPUT_KEYBOARD,
+            ki: KeybdInput {
+                wVk: virtual_key,
+                wScan: 0,
+                dwFlags: KEYEVENTF_EXTENDEDKEY,
+                time: 0,
+                dwExtraInfo: 0,
+                _padding: [0; 8],
+            },
+        },
+        KeyboardInput {
+            r#type: INPUT_KEYBOARD,
+            ki: KeybdInput {
+                wVk: virtual_key,
+                wScan: 0,
+                dwFlags: KEYEVENTF_EXTENDEDKEY | KEYEVENTF_KEYUP,
+                time: 0,
+                dwExtraInfo: 0,
+                _padding: [0; 8],
+            },
+        },
+    ];
+
+    unsafe {
+        let sent = SendInput(
+            2,
+            inputs.as_ptr(),
+            std::mem::size_of::<KeyboardInput>() as i32,
+        );
+        if sent == 2 {
+            info!("Simulated media {:?} (Windows)", action);
+        } else {
+            error!("SendInput failed, sent {} of 2 inputs", sent);
+        }
+    }
+}
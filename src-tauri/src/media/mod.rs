@@ -0,0 +1,331 @@
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Runtime};
+use tracing::{debug, warn};
+
+use crate::bridge::Bridge;
+use crate::protocol::{self, MessageKind};
+use crate::state::AppState;
+
+mod browser;
+mod keys;
+#[cfg(target_os = "linux")]
+mod mpris;
+mod mpv;
+#[cfg(test)]
+mod recording;
+#[cfg(target_os = "linux")]
+mod uinput;
+mod vlc;
+
+pub use browser::BrowserBackend;
+pub use keys::KeyBackend;
+#[cfg(target_os = "linux")]
+pub use mpris::MprisBackend;
+pub use mpv::MpvBackend;
+#[cfg(test)]
+pub use recording::{MediaCall, RecordingBackend};
+pub use vlc::{VlcBackend, VlcSettings};
+
+pub const COOLDOWN_MS: i64 = 500;
+
+/// Something that can drive the local media player.
+pub trait MediaBackend: Send + Sync {
+    fn name(&self) -> &'static str;
+    fn play(&self) -> Result<(), String>;
+    fn pause(&self) -> Result<(), String>;
+    fn toggle(&self) -> Result<(), String>;
+    fn seek(&self, position_ms: i64) -> Result<(), String>;
+    fn query(&self) -> Result<PlaybackState, String>;
+
+    /// Presses a media key. Backends without dedicated track or volume
+    /// controls only support play/pause.
+    fn perform(&self, action: MediaAction) -> Result<(), String> {
+        match action {
+            MediaAction::PlayPause => self.toggle(),
+            other => Err(format!("{} backend cannot {:?}", self.name(), other)),
+        }
+    }
+
+    /// Whether [`watch`](Self::watch) can observe the player.
+    fn can_watch(&self) -> bool {
+        false
+    }
+
+    /// Blocks, passing changes in the player to `report`, until `stop` is
+    /// set or the player can no longer be observed.
+    fn watch(&self, _report: &dyn Fn(PlayerChange), _stop: &AtomicBool) -> Result<(), String> {
+        Err(format!("{} backend cannot observe the player", self.name()))
+    }
+}
+
+/// A change noticed in the local player, whoever made it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PlayerChange {
+    Played,
+    Paused,
+    Seeked { position_ms: i64 },
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct PlaybackState {
+    pub playing: bool,
+    pub position_ms: Option<i64>,
+}
+
+/// Which backend drives playback.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum BackendKind {
+    Keys,
+    Mpris,
+    Mpv,
+    Vlc,
+    /// A video in a browser tab, through the companion extension.
+    Browser,
+    /// Records calls instead of touching a player, for tests.
+    #[cfg(test)]
+    Recording,
+}
+
+impl Default for BackendKind {
+    fn default() -> Self {
+        if cfg!(target_os = "linux") {
+            Self::Mpris
+        } else {
+            Self::Keys
+        }
+    }
+}
+
+/// Per-backend configuration.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct MediaSettings {
+    /// MPRIS player to control when several are running.
+    pub player: Option<String>,
+    /// mpv `--input-ipc-server` path.
+    pub mpv_socket: String,
+    pub vlc: VlcSettings,
+}
+
+impl Default for MediaSettings {
+    fn default() -> Self {
+        Self {
+            player: None,
+            mpv_socket: if cfg!(windows) {
+                r"\\.\pipe\mpvsocket".into()
+            } else {
+                "/tmp/mpvsocket".into()
+            },
+            vlc: VlcSettings::default(),
+        }
+    }
+}
+
+/// One entry in the set of backends every command fans out to.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct MediaTarget {
+    pub kind: BackendKind,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// Message types (`play`, `seek`, `volumeUp`, ...) this backend reacts
+    /// to. Empty means all of them.
+    #[serde(default)]
+    pub types: Vec<String>,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+impl MediaTarget {
+    pub fn new(kind: BackendKind) -> Self {
+        Self {
+            kind,
+            enabled: true,
+            types: Vec::new(),
+        }
+    }
+
+    pub fn accepts(&self, msg_type: &str) -> bool {
+        self.types.is_empty() || self.types.iter().any(|t| t == msg_type)
+    }
+}
+
+/// A backend that failed to carry out a command, for the event log.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BackendFailure {
+    backend: &'static str,
+    #[serde(rename = "type")]
+    msg_type: String,
+    error: String,
+}
+
+/// Builds a backend. `bridge` is shared so extension connections survive
+/// reconfiguring the backends.
+pub fn create(
+    kind: BackendKind,
+    settings: &MediaSettings,
+    bridge: &Arc<Bridge>,
+) -> Result<Arc<dyn MediaBackend>, String> {
+    match kind {
+        BackendKind::Keys => {
+            #[cfg(target_os = "linux")]
+            uinput::check_access()?;
+            Ok(Arc::new(KeyBackend))
+        }
+        #[cfg(target_os = "linux")]
+        BackendKind::Mpris => Ok(Arc::new(MprisBackend::new(settings.player.clone()))),
+        #[cfg(not(target_os = "linux"))]
+        BackendKind::Mpris => Err("MPRIS is only available on Linux".into()),
+        BackendKind::Mpv => Ok(Arc::new(MpvBackend::new(settings.mpv_socket.clone()))),
+        BackendKind::Vlc => Ok(Arc::new(VlcBackend::new(settings.vlc.clone()))),
+        BackendKind::Browser => Ok(Arc::new(BrowserBackend::new(bridge.clone()))),
+        // Shared with the tests, so `AppState` hands out its own
+        #[cfg(test)]
+        BackendKind::Recording => Err("the recording backend belongs to AppState".into()),
+    }
+}
+
+/// A media key we can press on the user's behalf.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum MediaAction {
+    PlayPause,
+    Next,
+    Previous,
+    Stop,
+    VolumeUp,
+    VolumeDown,
+    Mute,
+}
+
+impl MediaAction {
+    /// The room message that carries this action.
+    pub fn message_kind(self) -> MessageKind {
+        match self {
+            Self::PlayPause => MessageKind::Toggle,
+            Self::Next => MessageKind::Next,
+            Self::Previous => MessageKind::Previous,
+            Self::Stop => MessageKind::Stop,
+            Self::VolumeUp => MessageKind::VolumeUp,
+            Self::VolumeDown => MessageKind::VolumeDown,
+            Self::Mute => MessageKind::Mute,
+        }
+    }
+
+    /// The key to press for a room message, if it maps to one. Play and
+    /// pause both land on the play/pause key.
+    pub fn from_kind(kind: &MessageKind) -> Option<Self> {
+        match kind {
+            MessageKind::Toggle | MessageKind::Play | MessageKind::Pause => Some(Self::PlayPause),
+            MessageKind::Next => Some(Self::Next),
+            MessageKind::Previous => Some(Self::Previous),
+            MessageKind::Stop => Some(Self::Stop),
+            MessageKind::VolumeUp => Some(Self::VolumeUp),
+            MessageKind::VolumeDown => Some(Self::VolumeDown),
+            MessageKind::Mute => Some(Self::Mute),
+            _ => None,
+        }
+    }
+}
+
+pub fn simulate_toggle<R: Runtime>(app: &AppHandle<R>, state: &AppState) {
+    simulate(app, state, MediaAction::PlayPause);
+}
+
+pub fn simulate<R: Runtime>(app: &AppHandle<R>, state: &AppState, action: MediaAction) {
+    press(app, state, action.message_kind().type_name(), |media| {
+        media.perform(action).map(|_| true)
+    });
+}
+
+/// Whether the primary player is playing, or `None` if it can't tell.
+pub fn is_playing(state: &AppState) -> Option<bool> {
+    state.primary_media()?.query().ok().map(|s| s.playing)
+}
+
+/// Starts playback on every backend that isn't already playing. Returns
+/// how many had to act. Key simulation can't tell and always presses
+/// play/pause, so check [`is_playing`] first where it matters.
+pub fn play<R: Runtime>(app: &AppHandle<R>, state: &AppState) -> usize {
+    press(app, state, "play", |media| match media.query() {
+        Ok(s) if s.playing => Ok(false),
+        _ => media.play().map(|_| true),
+    })
+}
+
+/// Pauses playback; see [`play`].
+pub fn pause<R: Runtime>(app: &AppHandle<R>, state: &AppState) -> usize {
+    press(app, state, "pause", |media| match media.query() {
+        Ok(s) if !s.playing => Ok(false),
+        _ => media.pause().map(|_| true),
+    })
+}
+
+/// Seeks the local players to `position_ms`.
+pub fn seek<R: Runtime>(app: &AppHandle<R>, state: &AppState, position_ms: i64) {
+    state.note_media_command();
+    dispatch(app, state, "seek", |media| {
+        media.seek(position_ms).map(|_| true)
+    });
+}
+
+/// Media players we can target. Empty where key simulation goes to
+/// whichever app the OS picks.
+pub fn list_players() -> Result<Vec<String>, String> {
+    #[cfg(target_os = "linux")]
+    return mpris::list_players();
+
+    #[cfg(not(target_os = "linux"))]
+    Ok(Vec::new())
+}
+
+fn press<R: Runtime, F>(app: &AppHandle<R>, state: &AppState, msg_type: &str, action: F) -> usize
+where
+    F: Fn(&dyn MediaBackend) -> Result<bool, String>,
+{
+    // Set cooldown to prevent feedback loop
+    let until = protocol::now_ms() + COOLDOWN_MS;
+    state.set_cooldown(until);
+    state.note_media_command();
+    dispatch(app, state, msg_type, action)
+}
+
+/// Runs `action` on every enabled backend that reacts to `msg_type`,
+/// reporting each failure on its own. Returns how many backends acted.
+fn dispatch<R: Runtime, F>(app: &AppHandle<R>, state: &AppState, msg_type: &str, action: F) -> usize
+where
+    F: Fn(&dyn MediaBackend) -> Result<bool, String>,
+{
+    let backends = state.media_for(msg_type);
+    if backends.is_empty() {
+        debug!("No enabled backend reacts to {}", msg_type);
+    }
+
+    let mut acted = 0;
+    for media in backends {
+        match action(media.as_ref()) {
+            Ok(true) => acted += 1,
+            Ok(false) => debug!("{} backend already in place for {}", media.name(), msg_type),
+            Err(e) => {
+                warn!("{} via {} backend failed: {}", msg_type, media.name(), e);
+                let _ = app.emit(
+                    "media-error",
+                    BackendFailure {
+                        backend: media.name(),
+                        msg_type: msg_type.to_string(),
+                        error: e,
+                    },
+                );
+            }
+        }
+    }
+    acted
+}
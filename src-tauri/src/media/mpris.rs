@@ -0,0 +1,382 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use tracing::{debug, info};
+use zbus::blocking::fdo::DBusProxy;
+use zbus::blocking::{Connection, MessageIterator, Proxy};
+use zbus::message::Type as MessageType;
+use zbus::names::{BusName, OwnedUniqueName};
+use zbus::proxy::CacheProperties;
+use zbus::zvariant::{ObjectPath, OwnedObjectPath, OwnedValue};
+use zbus::{MatchRule, Message};
+
+use super::{KeyBackend, MediaAction, MediaBackend, PlaybackState, PlayerChange};
+
+const BUS_NAME_PREFIX: &str = "org.mpris.MediaPlayer2.";
+const OBJECT_PATH: &str = "/org/mpris/MediaPlayer2";
+const PLAYER_INTERFACE: &str = "org.mpris.MediaPlayer2.Player";
+
+/// Volume change per volume key press, on MPRIS's 0.0-1.0 scale.
+const VOLUME_STEP: f64 = 0.05;
+
+/// How often a watch checks whether it's been told to stop.
+const STOP_POLL: Duration = Duration::from_millis(250);
+
+/// Controls an MPRIS player, resolving which one on every call so players
+/// started or quit after us are picked up. With no player running at all,
+/// falls back to pressing media keys.
+pub struct MprisBackend {
+    preferred: Option<String>,
+    fallback: Option<Arc<dyn MediaBackend>>,
+}
+
+impl MprisBackend {
+    pub fn new(preferred: Option<String>) -> Self {
+        Self {
+            preferred,
+            fallback: Some(Arc::new(KeyBackend)),
+        }
+    }
+
+    /// Replaces the media key fallback; `None` fails instead.
+    pub fn with_fallback(mut self, fallback: Option<Arc<dyn MediaBackend>>) -> Self {
+        self.fallback = fallback;
+        self
+    }
+
+    fn player(&self) -> Result<Player, String> {
+        player(self.preferred.as_deref())?.ok_or_else(|| "No MPRIS player is running".into())
+    }
+
+    /// Runs `f` on the player, or has the fallback perform `fallback` when
+    /// there's no player to run it on.
+    fn control(
+        &self,
+        f: impl FnOnce(Player) -> Result<(), String>,
+        fallback: MediaAction,
+    ) -> Result<(), String> {
+        match (player(self.preferred.as_deref())?, &self.fallback) {
+            (Some(player), _) => f(player),
+            (None, Some(backend)) => {
+                debug!("No MPRIS player running, pressing {:?} instead", fallback);
+                backend.perform(fallback)
+            }
+            (None, None) => Err("No MPRIS player is running".into()),
+        }
+    }
+
+    /// Reports the changes `signals` announce until told to stop. Only the
+    /// player we control counts, judged both before and after the change:
+    /// pausing it may hand control to another player, and starting one
+    /// while nothing plays hands control to it.
+    fn follow(
+        &self,
+        signals: MessageIterator,
+        dbus: &DBusProxy,
+        report: &dyn Fn(PlayerChange),
+        stop: &AtomicBool,
+    ) -> Result<(), String> {
+        let mut selected = self.selected_owner(dbus);
+        for msg in signals {
+            if stop.load(Ordering::Relaxed) {
+                break;
+            }
+            let msg = msg.map_err(|e| e.to_string())?;
+            let change = match player_change(&msg) {
+                Ok(Some(change)) => change,
+                Ok(None) => continue,
+                Err(e) => {
+                    debug!("Ignoring malformed MPRIS signal: {}", e);
+                    continue;
+                }
+            };
+
+            let before = std::mem::replace(&mut selected, self.selected_owner(dbus));
+            let header = msg.header();
+            let Some(sender) = header.sender() else {
+                continue;
+            };
+            if [before, selected.clone()]
+                .iter()
+                .flatten()
+                .any(|owner| **owner == *sender)
+            {
+                report(change);
+            } else {
+                debug!("Ignoring {:?} from a player we don't control", change);
+            }
+        }
+        Ok(())
+    }
+
+    /// Unique bus name of the player commands would go to right now.
+    fn selected_owner(&self, dbus: &DBusProxy) -> Option<OwnedUniqueName> {
+        let player = player(self.preferred.as_deref()).ok()??;
+        let name = BusName::try_from(format!("{}{}", BUS_NAME_PREFIX, player.name)).ok()?;
+        dbus.get_name_owner(name).ok()
+    }
+}
+
+impl MediaBackend for MprisBackend {
+    fn name(&self) -> &'static str {
+        "mpris"
+    }
+
+    fn play(&self) -> Result<(), String> {
+        self.control(|p| p.play(), MediaAction::PlayPause)
+    }
+
+    fn pause(&self) -> Result<(), String> {
+        self.control(|p| p.pause(), MediaAction::PlayPause)
+    }
+
+    fn toggle(&self) -> Result<(), String> {
+        self.control(|p| p.play_pause(), MediaAction::PlayPause)
+    }
+
+    fn seek(&self, position_ms: i64) -> Result<(), String> {
+        self.player()?.seek(position_ms)
+    }
+
+    fn query(&self) -> Result<PlaybackState, String> {
+        let player = self.player()?;
+        Ok(PlaybackState {
+            playing: player.is_playing()?,
+            position_ms: player.position().ok(),
+        })
+    }
+
+    fn perform(&self, action: MediaAction) -> Result<(), String> {
+        self.control(|p| p.perform(action), action)
+    }
+
+    fn can_watch(&self) -> bool {
+        true
+    }
+
+    /// Follows `PropertiesChanged` and `Seeked` from the player we control.
+    fn watch(&self, report: &dyn Fn(PlayerChange), stop: &AtomicBool) -> Result<(), String> {
+        let conn = session()?;
+        let rule = MatchRule::builder()
+            .msg_type(MessageType::Signal)
+            .path(OBJECT_PATH)
+            .map_err(|e| e.to_string())?
+            .build();
+        let signals =
+            MessageIterator::for_match_rule(rule, &conn, None).map_err(|e| e.to_string())?;
+        let dbus = DBusProxy::new(&conn).map_err(|e| e.to_string())?;
+
+        let done = AtomicBool::new(false);
+        std::thread::scope(|scope| {
+            // Signals only come when a player changes, so waiting for the
+            // next one to notice `stop` could take forever. Closing the
+            // connection ends the iteration instead.
+            scope.spawn(|| {
+                while !stop.load(Ordering::Relaxed) && !done.load(Ordering::Relaxed) {
+                    std::thread::sleep(STOP_POLL);
+                }
+                if stop.load(Ordering::Relaxed) {
+                    let _ = conn.clone().close();
+                }
+            });
+            let result = self.follow(signals, &dbus, report, stop);
+            done.store(true, Ordering::Relaxed);
+            result
+        })
+    }
+}
+
+/// The playback change a player signal announces, if any.
+fn player_change(msg: &Message) -> Result<Option<PlayerChange>, String> {
+    let header = msg.header();
+    match header.member().map(|m| m.as_str()) {
+        Some("PropertiesChanged") => {
+            let (interface, mut changed, _): (String, HashMap<String, OwnedValue>, Vec<String>) =
+                msg.body().deserialize().map_err(|e| e.to_string())?;
+            if interface != PLAYER_INTERFACE {
+                return Ok(None);
+            }
+            let Some(status) = changed.remove("PlaybackStatus") else {
+                return Ok(None);
+            };
+            let status = String::try_from(status).map_err(|e| e.to_string())?;
+            Ok(Some(match status.as_str() {
+                "Playing" => PlayerChange::Played,
+                _ => PlayerChange::Paused,
+            }))
+        }
+        Some("Seeked") if header.interface().map(|i| i.as_str()) == Some(PLAYER_INTERFACE) => {
+            let position_us: i64 = msg.body().deserialize().map_err(|e| e.to_string())?;
+            Ok(Some(PlayerChange::Seeked {
+                position_ms: position_us / 1000,
+            }))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Short names (`vlc`, `spotify`, ...) of every MPRIS player on the bus.
+pub fn list_players() -> Result<Vec<String>, String> {
+    player_names(&session()?)
+}
+
+/// Connects to the player to control: `preferred` if it's running,
+/// otherwise whichever player is currently playing, otherwise the first.
+/// `None` when no player is running.
+pub fn player(preferred: Option<&str>) -> Result<Option<Player>, String> {
+    let conn = session()?;
+    let names = player_names(&conn)?;
+
+    if let Some(preferred) = preferred {
+        if let Some(name) = names.iter().find(|n| n.as_str() == preferred) {
+            return Player::connect(&conn, name).map(Some);
+        }
+        debug!("Preferred player {} not running", preferred);
+    }
+
+    let mut fallback = None;
+    for name in &names {
+        let player = Player::connect(&conn, name)?;
+        if player.is_playing()? {
+            return Ok(Some(player));
+        }
+        fallback.get_or_insert(player);
+    }
+    Ok(fallback)
+}
+
+/// Honours `DBUS_SESSION_BUS_ADDRESS`, so a private `dbus-daemon` with a
+/// fake player can stand in for the desktop session.
+fn session() -> Result<Connection, String> {
+    Connection::session().map_err(|e| e.to_string())
+}
+
+fn player_names(conn: &Connection) -> Result<Vec<String>, String> {
+    let dbus = DBusProxy::new(conn).map_err(|e| e.to_string())?;
+    let mut names: Vec<String> = dbus
+        .list_names()
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .filter_map(|name| name.strip_prefix(BUS_NAME_PREFIX).map(String::from))
+        .collect();
+    names.sort();
+    Ok(names)
+}
+
+/// One player's `org.mpris.MediaPlayer2.Player` interface.
+pub struct Player {
+    name: String,
+    proxy: Proxy<'static>,
+}
+
+impl Player {
+    fn connect(conn: &Connection, name: &str) -> Result<Self, String> {
+        // Playback status changes behind our back, so always read it fresh
+        let proxy = zbus::blocking::proxy::Builder::<Proxy>::new(conn)
+            .destination(format!("{}{}", BUS_NAME_PREFIX, name))
+            .and_then(|b| b.path(OBJECT_PATH))
+            .and_then(|b| b.interface(PLAYER_INTERFACE))
+            .map_err(|e| e.to_string())?
+            .cache_properties(CacheProperties::No)
+            .build()
+            .map_err(|e| e.to_string())?;
+
+        Ok(Self {
+            name: name.to_string(),
+            proxy,
+        })
+    }
+
+    pub fn play(&self) -> Result<(), String> {
+        self.call("Play")
+    }
+
+    pub fn pause(&self) -> Result<(), String> {
+        self.call("Pause")
+    }
+
+    pub fn play_pause(&self) -> Result<(), String> {
+        self.call("PlayPause")
+    }
+
+    pub fn is_playing(&self) -> Result<bool, String> {
+        let status: String = self
+            .proxy
+            .get_property("PlaybackStatus")
+            .map_err(|e| e.to_string())?;
+        Ok(status == "Playing")
+    }
+
+    /// Current position in ms.
+    pub fn position(&self) -> Result<i64, String> {
+        let position_us: i64 = self
+            .proxy
+            .get_property("Position")
+            .map_err(|e| e.to_string())?;
+        Ok(position_us / 1000)
+    }
+
+    /// Moves to an absolute position. Uses `SetPosition` when the player
+    /// exposes a track ID, falling back to a relative `Seek`.
+    pub fn seek(&self, position_ms: i64) -> Result<(), String> {
+        let position_us = position_ms * 1000;
+        if let Some(track_id) = self.track_id() {
+            let track_id = ObjectPath::from(&track_id);
+            return self
+                .proxy
+                .call_method("SetPosition", &(track_id, position_us))
+                .map(|_| ())
+                .map_err(|e| e.to_string());
+        }
+
+        let offset_us = position_us - self.position()? * 1000;
+        self.proxy
+            .call_method("Seek", &(offset_us,))
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+
+    /// Performs a media key action using the matching MPRIS call.
+    pub fn perform(&self, action: MediaAction) -> Result<(), String> {
+        match action {
+            MediaAction::PlayPause => self.play_pause(),
+            MediaAction::Next => self.call("Next"),
+            MediaAction::Previous => self.call("Previous"),
+            MediaAction::Stop => self.call("Stop"),
+            MediaAction::VolumeUp => self.adjust_volume(VOLUME_STEP),
+            MediaAction::VolumeDown => self.adjust_volume(-VOLUME_STEP),
+            // MPRIS has no mute, so drop to silence
+            MediaAction::Mute => self.set_volume(0.0),
+        }
+    }
+
+    fn adjust_volume(&self, delta: f64) -> Result<(), String> {
+        let volume: f64 = self
+            .proxy
+            .get_property("Volume")
+            .map_err(|e| e.to_string())?;
+        self.set_volume((volume + delta).clamp(0.0, 1.0))
+    }
+
+    fn set_volume(&self, volume: f64) -> Result<(), String> {
+        self.proxy
+            .set_property("Volume", volume)
+            .map_err(|e| e.to_string())
+    }
+
+    fn track_id(&self) -> Option<OwnedObjectPath> {
+        let metadata: HashMap<String, OwnedValue> = self.proxy.get_property("Metadata").ok()?;
+        let track_id = metadata.get("mpris:trackid")?.try_clone().ok()?;
+        OwnedObjectPath::try_from(track_id).ok()
+    }
+
+    fn call(&self, method: &str) -> Result<(), String> {
+        self.proxy
+            .call_method(method, &())
+            .map_err(|e| format!("{} on {} failed: {}", method, self.name, e))?;
+        info!("MPRIS {} on {}", method, self.name);
+        Ok(())
+    }
+}
@@ -0,0 +1,479 @@
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Duration;
+
+use serde_json::{json, Value};
+use tracing::debug;
+
+use super::{MediaAction, MediaBackend, PlaybackState, PlayerChange};
+
+const IO_TIMEOUT: Duration = Duration::from_secs(1);
+/// How often `watch` looks for new events on a named pipe, which can't
+/// time out a blocking read.
+#[cfg(windows)]
+const PIPE_POLL: Duration = Duration::from_millis(100);
+const VOLUME_STEP: i64 = 5;
+
+#[cfg(unix)]
+type Stream = std::os::unix::net::UnixStream;
+#[cfg(windows)]
+type Stream = std::fs::File;
+
+/// Drives mpv through its JSON IPC socket (`--input-ipc-server`). A fresh
+/// connection per command keeps us working across mpv restarts.
+pub struct MpvBackend {
+    socket: String,
+    next_request: AtomicU64,
+}
+
+impl MpvBackend {
+    pub fn new(socket: String) -> Self {
+        Self {
+            socket,
+            next_request: AtomicU64::new(1),
+        }
+    }
+
+    /// Sends one command and returns its `data`, skipping any events mpv
+    /// interleaves before the reply.
+    fn command(&self, args: Value) -> Result<Value, String> {
+        let request_id = self.next_request.fetch_add(1, Ordering::Relaxed);
+        let mut line = json!({ "command": args, "request_id": request_id }).to_string();
+        line.push('\n');
+
+        let stream = self.connect()?;
+        (&stream)
+            .write_all(line.as_bytes())
+            .map_err(|e| format!("write to mpv failed: {}", e))?;
+
+        let mut reader = BufReader::new(&stream);
+        let mut buf = String::new();
+        loop {
+            buf.clear();
+            let read = reader
+                .read_line(&mut buf)
+                .map_err(|e| format!("read from mpv failed: {}", e))?;
+            if read == 0 {
+                return Err("mpv closed the connection".into());
+            }
+
+            let reply: Value = match serde_json::from_str(&buf) {
+                Ok(v) => v,
+                Err(e) => {
+                    debug!("Ignoring unparseable mpv line: {}", e);
+                    continue;
+                }
+            };
+            if reply.get("request_id").and_then(Value::as_u64) != Some(request_id) {
+                continue;
+            }
+
+            return match reply.get("error").and_then(Value::as_str) {
+                Some("success") => Ok(reply.get("data").cloned().unwrap_or(Value::Null)),
+                Some(error) => Err(format!("mpv: {}", error)),
+                None => Err("mpv reply has no status".into()),
+            };
+        }
+    }
+
+    #[cfg(unix)]
+    fn connect(&self) -> Result<Stream, String> {
+        let stream = Stream::connect(&self.socket)
+            .map_err(|e| format!("cannot connect to mpv at {}: {}", self.socket, e))?;
+        stream
+            .set_read_timeout(Some(IO_TIMEOUT))
+            .and_then(|_| stream.set_write_timeout(Some(IO_TIMEOUT)))
+            .map_err(|e| e.to_string())?;
+        Ok(stream)
+    }
+
+    /// Named pipes open like files; they don't support timeouts.
+    #[cfg(windows)]
+    fn connect(&self) -> Result<Stream, String> {
+        std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&self.socket)
+            .map_err(|e| format!("cannot connect to mpv at {}: {}", self.socket, e))
+    }
+
+    fn set_pause(&self, paused: bool) -> Result<(), String> {
+        self.command(json!(["set_property", "pause", paused]))
+            .map(|_| ())
+    }
+}
+
+impl MediaBackend for MpvBackend {
+    fn name(&self) -> &'static str {
+        "mpv"
+    }
+
+    fn play(&self) -> Result<(), String> {
+        self.set_pause(false)
+    }
+
+    fn pause(&self) -> Result<(), String> {
+        self.set_pause(true)
+    }
+
+    fn toggle(&self) -> Result<(), String> {
+        self.command(json!(["cycle", "pause"])).map(|_| ())
+    }
+
+    fn seek(&self, position_ms: i64) -> Result<(), String> {
+        let seconds = position_ms as f64 / 1000.0;
+        self.command(json!(["seek", seconds, "absolute"]))
+            .map(|_| ())
+    }
+
+    fn query(&self) -> Result<PlaybackState, String> {
+        let paused = self
+            .command(json!(["get_property", "pause"]))?
+            .as_bool()
+            .ok_or("mpv reported a non-boolean pause state")?;
+        // No file loaded means no position
+        let position_ms = self
+            .command(json!(["get_property", "time-pos"]))
+            .ok()
+            .and_then(|v| v.as_f64())
+            .map(|seconds| (seconds * 1000.0) as i64);
+
+        Ok(PlaybackState {
+            playing: !paused,
+            position_ms,
+        })
+    }
+
+    fn perform(&self, action: MediaAction) -> Result<(), String> {
+        let args = match action {
+            MediaAction::PlayPause => return self.toggle(),
+            MediaAction::Next => json!(["playlist-next"]),
+            MediaAction::Previous => json!(["playlist-prev"]),
+            MediaAction::Stop => json!(["stop"]),
+            MediaAction::VolumeUp => json!(["add", "volume", VOLUME_STEP]),
+            MediaAction::VolumeDown => json!(["add", "volume", -VOLUME_STEP]),
+            MediaAction::Mute => json!(["cycle", "mute"]),
+        };
+        self.command(args).map(|_| ())
+    }
+
+    fn can_watch(&self) -> bool {
+        true
+    }
+
+    /// Observes `pause` on a connection of its own. Seeks are reported once
+    /// playback restarts, when `time-pos` has settled on the new position.
+    fn watch(&self, report: &dyn Fn(PlayerChange), stop: &AtomicBool) -> Result<(), String> {
+        let stream = self.connect()?;
+        (&stream)
+            .write_all(b"{\"command\": [\"observe_property\", 1, \"pause\"]}\n")
+            .map_err(|e| format!("write to mpv failed: {}", e))?;
+
+        let mut reader = BufReader::new(&stream);
+        let mut buf = String::new();
+        // mpv reports the current value straight away; that's not a change
+        let mut paused: Option<bool> = None;
+        let mut seeking = false;
+        while !stop.load(Ordering::Relaxed) {
+            // Pipe reads block for good, so only read once mpv has
+            // written something and keep checking `stop` meanwhile
+            #[cfg(windows)]
+            if reader.buffer().is_empty() && !pipe_has_data(reader.get_ref())? {
+                std::thread::sleep(PIPE_POLL);
+                continue;
+            }
+            match reader.read_line(&mut buf) {
+                Ok(0) => return Err("mpv closed the connection".into()),
+                Ok(_) => {}
+                // Read timeouts just give us a chance to check `stop`
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                    continue;
+                }
+                Err(e) => return Err(format!("read from mpv failed: {}", e)),
+            }
+            let event: Value = match serde_json::from_str(&buf) {
+                Ok(v) => v,
+                Err(e) => {
+                    debug!("Ignoring unparseable mpv line: {}", e);
+                    buf.clear();
+                    continue;
+                }
+            };
+            buf.clear();
+
+            match event.get("event").and_then(Value::as_str) {
+                Some("property-change") if event.get("name") == Some(&json!("pause")) => {
+                    let Some(now) = event.get("data").and_then(Value::as_bool) else {
+                        continue;
+                    };
+                    if paused.is_some_and(|was| was != now) {
+                        report(if now {
+                            PlayerChange::Paused
+                        } else {
+                            PlayerChange::Played
+                        });
+                    }
+                    paused = Some(now);
+                }
+                Some("seek") => seeking = true,
+                Some("playback-restart") if seeking => {
+                    seeking = false;
+                    if let Some(seconds) = self
+                        .command(json!(["get_property", "time-pos"]))
+                        .ok()
+                        .and_then(|v| v.as_f64())
+                    {
+                        report(PlayerChange::Seeked {
+                            position_ms: (seconds * 1000.0) as i64,
+                        });
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Whether mpv has written anything we haven't read yet.
+#[cfg(windows)]
+fn pipe_has_data(pipe: &Stream) -> Result<bool, String> {
+    use std::ffi::c_void;
+    use std::os::windows::io::AsRawHandle;
+    use std::ptr::null_mut;
+
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        fn PeekNamedPipe(
+            pipe: *mut c_void,
+            buffer: *mut c_void,
+            buffer_size: u32,
+            bytes_read: *mut u32,
+            total_bytes_avail: *mut u32,
+            bytes_left_this_message: *mut u32,
+        ) -> i32;
+    }
+
+    let mut available: u32 = 0;
+    // SAFETY: the handle stays open for the call, and the only out pointer
+    // is to a local
+    let ok = unsafe {
+        PeekNamedPipe(
+            pipe.as_raw_handle(),
+            null_mut(),
+            0,
+            null_mut(),
+            &mut available,
+            null_mut(),
+        )
+    };
+    if ok == 0 {
+        return Err(format!(
+            "read from mpv failed: {}",
+            std::io::Error::last_os_error()
+        ));
+    }
+    Ok(available > 0)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::os::unix::net::UnixListener;
+    use std::path::PathBuf;
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+
+    static NEXT_SOCKET: AtomicU64 = AtomicU64::new(0);
+
+    /// Stand-in for mpv's IPC socket. Logs each command and answers it
+    /// with `reply`, after an event and a reply to some other request.
+    /// Commands without a request id (`observe_property`) get `events`
+    /// instead, on a connection that then stays open.
+    struct FakeMpv {
+        path: PathBuf,
+        commands: Arc<Mutex<Vec<Value>>>,
+    }
+
+    impl FakeMpv {
+        fn start(reply: fn(&Value) -> Result<Value, &'static str>) -> Self {
+            Self::with_events(reply, Vec::new())
+        }
+
+        fn with_events(
+            reply: fn(&Value) -> Result<Value, &'static str>,
+            events: Vec<Value>,
+        ) -> Self {
+            let path = std::env::temp_dir().join(format!(
+                "mediasync-mpv-{}-{}.sock",
+                std::process::id(),
+                NEXT_SOCKET.fetch_add(1, Ordering::Relaxed)
+            ));
+            let listener = UnixListener::bind(&path).unwrap();
+            let commands = Arc::new(Mutex::new(Vec::new()));
+
+            let log = commands.clone();
+            std::thread::spawn(move || {
+                let mut observers = Vec::new();
+                for stream in listener.incoming() {
+                    let stream = stream.unwrap();
+                    let mut line = String::new();
+                    if BufReader::new(&stream).read_line(&mut line).unwrap() == 0 {
+                        continue;
+                    }
+                    let request: Value = serde_json::from_str(&line).unwrap();
+                    let command = request["command"].clone();
+                    log.lock().unwrap().push(command.clone());
+
+                    let mut out = &stream;
+                    let Some(request_id) = request["request_id"].as_u64() else {
+                        for event in &events {
+                            writeln!(out, "{}", event).unwrap();
+                        }
+                        observers.push(stream);
+                        continue;
+                    };
+
+                    let answer = match reply(&command) {
+                        Ok(data) => {
+                            json!({"request_id": request_id, "error": "success", "data": data})
+                        }
+                        Err(error) => json!({"request_id": request_id, "error": error}),
+                    };
+                    let stale = json!({"request_id": request_id + 100, "error": "success", "data": "stale"});
+                    for line in [json!({"event": "idle"}), stale, answer] {
+                        writeln!(out, "{}", line).unwrap();
+                    }
+                }
+            });
+
+            Self { path, commands }
+        }
+
+        fn backend(&self) -> MpvBackend {
+            MpvBackend::new(self.path.to_string_lossy().into_owned())
+        }
+
+        fn commands(&self) -> Vec<Value> {
+            self.commands.lock().unwrap().clone()
+        }
+    }
+
+    impl Drop for FakeMpv {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+
+    #[test]
+    fn sends_pause_and_absolute_seek() {
+        let mpv = FakeMpv::start(|_| Ok(Value::Null));
+        let backend = mpv.backend();
+
+        backend.play().unwrap();
+        backend.pause().unwrap();
+        backend.seek(90_500).unwrap();
+
+        assert_eq!(
+            mpv.commands(),
+            vec![
+                json!(["set_property", "pause", false]),
+                json!(["set_property", "pause", true]),
+                json!(["seek", 90.5, "absolute"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn query_reads_pause_and_position() {
+        let mpv = FakeMpv::start(|command| match command[1].as_str() {
+            Some("pause") => Ok(json!(false)),
+            Some("time-pos") => Ok(json!(12.25)),
+            _ => Err("unknown"),
+        });
+
+        assert_eq!(
+            mpv.backend().query().unwrap(),
+            PlaybackState {
+                playing: true,
+                position_ms: Some(12_250),
+            }
+        );
+    }
+
+    #[test]
+    fn query_without_a_file_has_no_position() {
+        let mpv = FakeMpv::start(|command| match command[1].as_str() {
+            Some("pause") => Ok(json!(true)),
+            _ => Err("property unavailable"),
+        });
+
+        assert_eq!(
+            mpv.backend().query().unwrap(),
+            PlaybackState {
+                playing: false,
+                position_ms: None,
+            }
+        );
+    }
+
+    #[test]
+    fn errors_are_reported() {
+        let mpv = FakeMpv::start(|_| Err("invalid parameter"));
+        assert_eq!(mpv.backend().play(), Err("mpv: invalid parameter".into()));
+    }
+
+    #[test]
+    fn watch_reports_pauses_and_settled_seeks() {
+        let mpv = FakeMpv::with_events(
+            |command| match command[1].as_str() {
+                Some("time-pos") => Ok(json!(42.5)),
+                _ => Err("unknown"),
+            },
+            vec![
+                // The current value, sent on observing
+                json!({"event": "property-change", "id": 1, "name": "pause", "data": true}),
+                json!({"event": "property-change", "id": 1, "name": "pause", "data": false}),
+                json!({"event": "seek"}),
+                json!({"event": "playback-restart"}),
+                json!({"event": "property-change", "id": 1, "name": "volume", "data": 80}),
+                json!({"event": "property-change", "id": 1, "name": "pause", "data": true}),
+                // Restarting without a seek, e.g. after loading a file
+                json!({"event": "playback-restart"}),
+            ],
+        );
+        let backend = mpv.backend();
+        let changes = Mutex::new(Vec::new());
+        let stop = AtomicBool::new(false);
+
+        let result = std::thread::scope(|scope| {
+            let watcher = scope
+                .spawn(|| backend.watch(&|change| changes.lock().unwrap().push(change), &stop));
+            let deadline = std::time::Instant::now() + Duration::from_secs(2);
+            while changes.lock().unwrap().len() < 3 && std::time::Instant::now() < deadline {
+                std::thread::sleep(Duration::from_millis(10));
+            }
+            stop.store(true, Ordering::Relaxed);
+            watcher.join().unwrap()
+        });
+
+        assert_eq!(result, Ok(()));
+        assert_eq!(
+            changes.into_inner().unwrap(),
+            vec![
+                PlayerChange::Played,
+                PlayerChange::Seeked {
+                    position_ms: 42_500
+                },
+                PlayerChange::Paused,
+            ]
+        );
+        assert_eq!(
+            mpv.commands(),
+            vec![
+                json!(["observe_property", 1, "pause"]),
+                json!(["get_property", "time-pos"]),
+            ]
+        );
+    }
+}
@@ -0,0 +1,83 @@
+use parking_lot::Mutex;
+use tracing::debug;
+
+use super::{MediaAction, MediaBackend, PlaybackState};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MediaCall {
+    Play,
+    Pause,
+    Toggle,
+    Seek { position_ms: i64 },
+    Perform { action: MediaAction },
+}
+
+/// Records every call instead of touching a player, keeping the playback
+/// state those calls imply so `query` answers consistently. Makes message
+/// handling observable in tests without a real player.
+#[derive(Default)]
+pub struct RecordingBackend {
+    calls: Mutex<Vec<MediaCall>>,
+    state: Mutex<PlaybackState>,
+}
+
+impl RecordingBackend {
+    pub fn calls(&self) -> Vec<MediaCall> {
+        self.calls.lock().clone()
+    }
+
+    fn record(&self, call: MediaCall) {
+        debug!("Recorded {:?}", call);
+        self.calls.lock().push(call);
+    }
+}
+
+impl MediaBackend for RecordingBackend {
+    fn name(&self) -> &'static str {
+        "recording"
+    }
+
+    fn play(&self) -> Result<(), String> {
+        self.record(MediaCall::Play);
+        self.state.lock().playing = true;
+        Ok(())
+    }
+
+    fn pause(&self) -> Result<(), String> {
+        self.record(MediaCall::Pause);
+        self.state.lock().playing = false;
+        Ok(())
+    }
+
+    fn toggle(&self) -> Result<(), String> {
+        self.record(MediaCall::Toggle);
+        let mut state = self.state.lock();
+        state.playing = !state.playing;
+        Ok(())
+    }
+
+    fn seek(&self, position_ms: i64) -> Result<(), String> {
+        self.record(MediaCall::Seek { position_ms });
+        self.state.lock().position_ms = Some(position_ms);
+        Ok(())
+    }
+
+    fn query(&self) -> Result<PlaybackState, String> {
+        Ok(*self.state.lock())
+    }
+
+    fn perform(&self, action: MediaAction) -> Result<(), String> {
+        match action {
+            MediaAction::PlayPause => self.toggle(),
+            MediaAction::Stop => {
+                self.record(MediaCall::Perform { action });
+                *self.state.lock() = PlaybackState::default();
+                Ok(())
+            }
+            action => {
+                self.record(MediaCall::Perform { action });
+                Ok(())
+            }
+        }
+    }
+}
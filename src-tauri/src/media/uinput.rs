@@ -0,0 +1,165 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, ErrorKind, Write};
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::OpenOptionsExt;
+use std::time::Duration;
+
+use parking_lot::Mutex;
+use tracing::info;
+
+use super::MediaAction;
+
+const UINPUT_PATH: &str = "/dev/uinput";
+const DEVICE_NAME: &[u8] = b"DragonFox MediaSync media keys";
+
+// linux/input-event-codes.h
+const EV_SYN: u16 = 0x00;
+const EV_KEY: u16 = 0x01;
+const SYN_REPORT: u16 = 0;
+const BUS_VIRTUAL: u16 = 0x06;
+const KEY_MUTE: u16 = 113;
+const KEY_VOLUMEDOWN: u16 = 114;
+const KEY_VOLUMEUP: u16 = 115;
+const KEY_NEXTSONG: u16 = 163;
+const KEY_PLAYPAUSE: u16 = 164;
+const KEY_PREVIOUSSONG: u16 = 165;
+const KEY_STOPCD: u16 = 166;
+
+// linux/uinput.h
+const UI_DEV_CREATE: libc::Ioctl = libc::_IO(b'U' as u32, 1);
+const UI_DEV_SETUP: libc::Ioctl = libc::_IOW::<libc::uinput_setup>(b'U' as u32, 3);
+const UI_SET_EVBIT: libc::Ioctl = libc::_IOW::<libc::c_int>(b'U' as u32, 100);
+const UI_SET_KEYBIT: libc::Ioctl = libc::_IOW::<libc::c_int>(b'U' as u32, 101);
+
+/// Keys the device declares; it can't send anything else.
+const KEYS: &[u16] = &[
+    KEY_PLAYPAUSE,
+    KEY_NEXTSONG,
+    KEY_PREVIOUSSONG,
+    KEY_STOPCD,
+    KEY_VOLUMEUP,
+    KEY_VOLUMEDOWN,
+    KEY_MUTE,
+];
+
+/// Time for the desktop to pick up a freshly created device. Keys sent
+/// before that are silently dropped.
+const SETTLE: Duration = Duration::from_millis(250);
+
+/// Kept for the life of the process so only the first press pays `SETTLE`.
+static DEVICE: Mutex<Option<File>> = Mutex::new(None);
+
+/// Checks that we may create input devices, without creating one.
+pub fn check_access() -> Result<(), String> {
+    open().map(|_| ())
+}
+
+/// Presses and releases the media key for `action` on a virtual keyboard,
+/// which reaches whatever the desktop routes media keys to under both X11
+/// and Wayland.
+pub fn press(action: MediaAction) -> Result<(), String> {
+    let mut device = DEVICE.lock();
+    let file = match device.as_mut() {
+        Some(file) => file,
+        None => {
+            let file = create()?;
+            std::thread::sleep(SETTLE);
+            device.insert(file)
+        }
+    };
+
+    let key = key_code(action);
+    let result = emit(file, EV_KEY, key, 1)
+        .and_then(|_| emit(file, EV_SYN, SYN_REPORT, 0))
+        .and_then(|_| emit(file, EV_KEY, key, 0))
+        .and_then(|_| emit(file, EV_SYN, SYN_REPORT, 0));
+    if let Err(e) = result {
+        // Recreate the device next time rather than keep writing to a dead one
+        *device = None;
+        return Err(format!("uinput write failed: {}", e));
+    }
+    info!("Simulated media {:?} (uinput)", action);
+    Ok(())
+}
+
+fn key_code(action: MediaAction) -> u16 {
+    match action {
+        MediaAction::PlayPause => KEY_PLAYPAUSE,
+        MediaAction::Next => KEY_NEXTSONG,
+        MediaAction::Previous => KEY_PREVIOUSSONG,
+        MediaAction::Stop => KEY_STOPCD,
+        MediaAction::VolumeUp => KEY_VOLUMEUP,
+        MediaAction::VolumeDown => KEY_VOLUMEDOWN,
+        MediaAction::Mute => KEY_MUTE,
+    }
+}
+
+fn open() -> Result<File, String> {
+    OpenOptions::new()
+        .write(true)
+        .custom_flags(libc::O_NONBLOCK)
+        .open(UINPUT_PATH)
+        .map_err(|e| match e.kind() {
+            ErrorKind::PermissionDenied => format!(
+                "no permission to open {}; grant your user access with a udev rule \
+                 (e.g. KERNEL==\"uinput\", GROUP=\"input\", MODE=\"0660\")",
+                UINPUT_PATH
+            ),
+            ErrorKind::NotFound => format!(
+                "{} does not exist; load the uinput kernel module",
+                UINPUT_PATH
+            ),
+            _ => format!("cannot open {}: {}", UINPUT_PATH, e),
+        })
+}
+
+fn create() -> Result<File, String> {
+    let file = open()?;
+    let fd = file.as_raw_fd();
+
+    // SAFETY: uinput_setup is plain old data, so all-zeroes is valid
+    let mut setup: libc::uinput_setup = unsafe { std::mem::zeroed() };
+    setup.id.bustype = BUS_VIRTUAL;
+    for (dst, src) in setup.name.iter_mut().zip(DEVICE_NAME) {
+        *dst = *src as libc::c_char;
+    }
+
+    let ioctl = |request, arg: libc::c_ulong| {
+        // SAFETY: fd is open for the duration of the call, and each request
+        // gets the argument type linux/uinput.h declares for it
+        match unsafe { libc::ioctl(fd, request, arg) } {
+            -1 => Err(format!(
+                "uinput setup failed: {}",
+                io::Error::last_os_error()
+            )),
+            _ => Ok(()),
+        }
+    };
+    ioctl(UI_SET_EVBIT, EV_KEY.into())?;
+    for &key in KEYS {
+        ioctl(UI_SET_KEYBIT, key.into())?;
+    }
+    ioctl(UI_DEV_SETUP, &setup as *const _ as libc::c_ulong)?;
+    ioctl(UI_DEV_CREATE, 0)?;
+
+    info!("Created uinput media key device");
+    Ok(file)
+}
+
+fn emit(file: &mut File, kind: u16, code: u16, value: i32) -> io::Result<()> {
+    // SAFETY: input_event is plain old data; a zero timestamp tells the
+    // kernel to fill in the current time
+    let mut event: libc::input_event = unsafe { std::mem::zeroed() };
+    event.type_ = kind;
+    event.code = code;
+    event.value = value;
+
+    // SAFETY: reads exactly the bytes of `event`, which outlives the slice
+    let bytes = unsafe {
+        std::slice::from_raw_parts(
+            &event as *const libc::input_event as *const u8,
+            std::mem::size_of::<libc::input_event>(),
+        )
+    };
+    file.write_all(bytes)
+}
@@ -0,0 +1,564 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::debug;
+
+use super::{MediaAction, MediaBackend, PlaybackState};
+
+const IO_TIMEOUT: Duration = Duration::from_secs(1);
+/// Unknown command sent after each RC command. VLC complains about it by
+/// name, which marks the end of the reply before it.
+const RC_SENTINEL: &str = "mediasync-end";
+/// Volume change per key press, on VLC's 0-512 scale where 256 is 100%.
+const VOLUME_STEP: i32 = 13;
+/// Volume to unmute to when we don't know what it was before.
+const DEFAULT_VOLUME: i64 = 256;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum VlcInterface {
+    /// `--extraintf http`, the web interface's JSON status endpoint.
+    Http,
+    /// `--extraintf rc --rc-host host:port`, the line-based remote control.
+    Rc,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct VlcSettings {
+    pub interface: VlcInterface,
+    pub host: String,
+    pub port: u16,
+    /// HTTP interface password; VLC refuses the HTTP interface without one.
+    pub password: String,
+}
+
+impl Default for VlcSettings {
+    fn default() -> Self {
+        Self {
+            interface: VlcInterface::Http,
+            host: "127.0.0.1".into(),
+            port: 8080,
+            password: String::new(),
+        }
+    }
+}
+
+/// Controls VLC through its HTTP or RC interface, which work whether or
+/// not VLC has focus.
+pub struct VlcBackend {
+    settings: VlcSettings,
+    /// Volume before we muted, to restore on the next mute.
+    muted_volume: Mutex<Option<i64>>,
+}
+
+impl VlcBackend {
+    pub fn new(settings: VlcSettings) -> Self {
+        Self {
+            settings,
+            muted_volume: Mutex::new(None),
+        }
+    }
+
+    fn connect(&self) -> Result<TcpStream, String> {
+        let addr = (self.settings.host.as_str(), self.settings.port)
+            .to_socket_addrs()
+            .map_err(|e| e.to_string())?
+            .next()
+            .ok_or_else(|| format!("cannot resolve {}", self.settings.host))?;
+        let stream = TcpStream::connect_timeout(&addr, IO_TIMEOUT)
+            .map_err(|e| format!("cannot connect to VLC at {}: {}", addr, e))?;
+        stream
+            .set_read_timeout(Some(IO_TIMEOUT))
+            .and_then(|_| stream.set_write_timeout(Some(IO_TIMEOUT)))
+            .map_err(|e| e.to_string())?;
+        Ok(stream)
+    }
+
+    /// Runs an HTTP interface command and returns the resulting status.
+    fn http(&self, query: &str) -> Result<Value, String> {
+        let mut stream = self.connect()?;
+        let credentials = STANDARD.encode(format!(":{}", self.settings.password));
+        // HTTP/1.0 so the body comes back unchunked and the server closes
+        let request = format!(
+            "GET /requests/status.json{} HTTP/1.0\r\nHost: {}:{}\r\nAuthorization: Basic {}\r\n\r\n",
+            query, self.settings.host, self.settings.port, credentials
+        );
+        stream
+            .write_all(request.as_bytes())
+            .map_err(|e| format!("write to VLC failed: {}", e))?;
+
+        let mut response = String::new();
+        stream
+            .read_to_string(&mut response)
+            .map_err(|e| format!("read from VLC failed: {}", e))?;
+
+        let (head, body) = response
+            .split_once("\r\n\r\n")
+            .ok_or("malformed HTTP response from VLC")?;
+        let status = head
+            .split_whitespace()
+            .nth(1)
+            .and_then(|code| code.parse::<u16>().ok())
+            .ok_or("malformed HTTP status line from VLC")?;
+        match status {
+            200 => serde_json::from_str(body).map_err(|e| e.to_string()),
+            401 | 403 => Err("VLC rejected the HTTP password".into()),
+            code => Err(format!("VLC responded with HTTP {}", code)),
+        }
+    }
+
+    fn http_command(&self, command: &str) -> Result<(), String> {
+        self.http(&format!("?command={}", command)).map(|_| ())
+    }
+
+    /// Sends one RC command. Returns whatever VLC printed in reply, with
+    /// prompts stripped; most commands print nothing.
+    fn rc(&self, command: &str) -> Result<Vec<String>, String> {
+        let mut stream = self.connect()?;
+        stream
+            .write_all(format!("{}\n{}\n", command, RC_SENTINEL).as_bytes())
+            .map_err(|e| format!("write to VLC failed: {}", e))?;
+
+        let mut lines = Vec::new();
+        let mut reader = BufReader::new(stream);
+        let mut buf = String::new();
+        loop {
+            let n = reader
+                .read_line(&mut buf)
+                .map_err(|e| format!("read from VLC failed: {}", e))?;
+            if n == 0 {
+                return Err(format!("VLC closed the connection replying to {}", command));
+            }
+            let line = buf.trim().trim_start_matches('>').trim();
+            if line.contains(RC_SENTINEL) {
+                break;
+            }
+            if !line.is_empty() {
+                lines.push(line.to_string());
+            }
+            buf.clear();
+        }
+        debug!("VLC RC {} -> {:?}", command, lines);
+        Ok(lines)
+    }
+
+    /// First integer VLC printed in reply to an RC query.
+    fn rc_number(&self, command: &str) -> Result<i64, String> {
+        self.rc(command)?
+            .iter()
+            .find_map(|line| line.parse().ok())
+            .ok_or_else(|| format!("no answer from VLC to {}", command))
+    }
+
+    fn volume(&self) -> Result<i64, String> {
+        match self.settings.interface {
+            VlcInterface::Http => self
+                .http("")?
+                .get("volume")
+                .and_then(Value::as_f64)
+                .map(|volume| volume as i64)
+                .ok_or_else(|| "VLC did not report its volume".into()),
+            VlcInterface::Rc => self.rc_number("volume"),
+        }
+    }
+
+    fn set_volume(&self, volume: i64) -> Result<(), String> {
+        match self.settings.interface {
+            VlcInterface::Http => self.http_command(&format!("volume&val={}", volume)),
+            VlcInterface::Rc => self.rc(&format!("volume {}", volume)).map(|_| ()),
+        }
+    }
+
+    /// Neither interface has a mute command, so mute by turning the volume
+    /// down to 0 and unmute by turning it back up.
+    fn toggle_mute(&self) -> Result<(), String> {
+        let volume = self.volume()?;
+        let target = if volume == 0 {
+            self.muted_volume.lock().take().unwrap_or(DEFAULT_VOLUME)
+        } else {
+            *self.muted_volume.lock() = Some(volume);
+            0
+        };
+        self.set_volume(target)
+    }
+}
+
+impl MediaBackend for VlcBackend {
+    fn name(&self) -> &'static str {
+        "vlc"
+    }
+
+    fn play(&self) -> Result<(), String> {
+        match self.settings.interface {
+            VlcInterface::Http => self.http_command("pl_forceresume"),
+            VlcInterface::Rc => self.rc("play").map(|_| ()),
+        }
+    }
+
+    fn pause(&self) -> Result<(), String> {
+        match self.settings.interface {
+            VlcInterface::Http => self.http_command("pl_forcepause"),
+            // RC's pause toggles, so only send it while playing
+            VlcInterface::Rc => match self.rc_number("is_playing")? {
+                0 => Ok(()),
+                _ => self.rc("pause").map(|_| ()),
+            },
+        }
+    }
+
+    fn toggle(&self) -> Result<(), String> {
+        match self.settings.interface {
+            VlcInterface::Http => self.http_command("pl_pause"),
+            VlcInterface::Rc => self.rc("pause").map(|_| ()),
+        }
+    }
+
+    /// VLC seeks in whole seconds.
+    fn seek(&self, position_ms: i64) -> Result<(), String> {
+        let seconds = position_ms / 1000;
+        match self.settings.interface {
+            VlcInterface::Http => self.http_command(&format!("seek&val={}", seconds)),
+            VlcInterface::Rc => self.rc(&format!("seek {}", seconds)).map(|_| ()),
+        }
+    }
+
+    fn query(&self) -> Result<PlaybackState, String> {
+        match self.settings.interface {
+            VlcInterface::Http => {
+                let status = self.http("")?;
+                Ok(PlaybackState {
+                    playing: status.get("state").and_then(Value::as_str) == Some("playing"),
+                    position_ms: status
+                        .get("time")
+                        .and_then(Value::as_i64)
+                        .map(|seconds| seconds * 1000),
+                })
+            }
+            VlcInterface::Rc => Ok(PlaybackState {
+                playing: self.rc_number("is_playing")? != 0,
+                position_ms: self.rc_number("get_time").ok().map(|s| s * 1000),
+            }),
+        }
+    }
+
+    fn perform(&self, action: MediaAction) -> Result<(), String> {
+        match self.settings.interface {
+            VlcInterface::Http => {
+                let command = match action {
+                    MediaAction::PlayPause => return self.toggle(),
+                    MediaAction::Next => "pl_next".to_string(),
+                    MediaAction::Previous => "pl_previous".to_string(),
+                    MediaAction::Stop => "pl_stop".to_string(),
+                    // `+` has to be escaped in a query string
+                    MediaAction::VolumeUp => format!("volume&val=%2B{}", VOLUME_STEP),
+                    MediaAction::VolumeDown => format!("volume&val=-{}", VOLUME_STEP),
+                    MediaAction::Mute => return self.toggle_mute(),
+                };
+                self.http_command(&command)
+            }
+            VlcInterface::Rc => {
+                let command = match action {
+                    MediaAction::PlayPause => return self.toggle(),
+                    MediaAction::Next => "next",
+                    MediaAction::Previous => "prev",
+                    MediaAction::Stop => "stop",
+                    MediaAction::VolumeUp => "volup 1",
+                    MediaAction::VolumeDown => "voldown 1",
+                    MediaAction::Mute => return self.toggle_mute(),
+                };
+                self.rc(command).map(|_| ())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+    use std::sync::atomic::{AtomicI64, Ordering};
+    use std::sync::Arc;
+    use std::time::Instant;
+
+    use super::*;
+
+    /// Stand-in for VLC's HTTP or RC interface. Logs the first line of
+    /// every request and answers it with `reply`, which sees the request
+    /// up to the blank line for HTTP, or its one command for RC. RC
+    /// replies end in VLC's complaint about the sentinel.
+    struct StandIn {
+        port: u16,
+        requests: Arc<Mutex<Vec<String>>>,
+        interface: VlcInterface,
+    }
+
+    impl StandIn {
+        fn start(interface: VlcInterface, reply: fn(&str) -> String) -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let port = listener.local_addr().unwrap().port();
+            let requests = Arc::new(Mutex::new(Vec::new()));
+
+            let log = requests.clone();
+            std::thread::spawn(move || {
+                for stream in listener.incoming() {
+                    let mut stream = stream.unwrap();
+                    let mut reader = BufReader::new(&stream);
+                    let mut request = String::new();
+                    loop {
+                        let mut line = String::new();
+                        if reader.read_line(&mut line).unwrap() == 0 {
+                            break;
+                        }
+                        if line.trim() == RC_SENTINEL {
+                            break;
+                        }
+                        request.push_str(&line);
+                        if line == "\r\n" {
+                            break;
+                        }
+                    }
+                    let first = request.lines().next().unwrap_or_default();
+                    log.lock().push(first.to_string());
+                    let mut answer = reply(&request);
+                    if interface == VlcInterface::Rc {
+                        answer.push_str(&format!(
+                            "Unknown command `{}'. Type `help' for help.\r\n> ",
+                            RC_SENTINEL
+                        ));
+                    }
+                    stream.write_all(answer.as_bytes()).unwrap();
+                }
+            });
+
+            Self {
+                port,
+                requests,
+                interface,
+            }
+        }
+
+        fn backend(&self, password: &str) -> VlcBackend {
+            VlcBackend::new(VlcSettings {
+                interface: self.interface,
+                host: "127.0.0.1".into(),
+                port: self.port,
+                password: password.into(),
+            })
+        }
+
+        fn requests(&self) -> Vec<String> {
+            std::mem::take(&mut *self.requests.lock())
+        }
+    }
+
+    fn ok(body: &str) -> String {
+        format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n\r\n{}",
+            body
+        )
+    }
+
+    #[test]
+    fn http_status_is_parsed() {
+        let vlc = StandIn::start(VlcInterface::Http, |_| {
+            ok(r#"{"state": "playing", "time": 42, "length": 300}"#)
+        });
+
+        assert_eq!(
+            vlc.backend("").query().unwrap(),
+            PlaybackState {
+                playing: true,
+                position_ms: Some(42_000),
+            }
+        );
+        assert_eq!(vlc.requests(), ["GET /requests/status.json HTTP/1.0"]);
+    }
+
+    #[test]
+    fn http_commands_are_escaped() {
+        let vlc = StandIn::start(VlcInterface::Http, |_| ok("{}"));
+        let backend = vlc.backend("");
+
+        backend.play().unwrap();
+        backend.seek(90_900).unwrap();
+        backend.perform(MediaAction::VolumeUp).unwrap();
+        backend.perform(MediaAction::VolumeDown).unwrap();
+
+        assert_eq!(
+            vlc.requests(),
+            [
+                "GET /requests/status.json?command=pl_forceresume HTTP/1.0",
+                "GET /requests/status.json?command=seek&val=90 HTTP/1.0",
+                "GET /requests/status.json?command=volume&val=%2B13 HTTP/1.0",
+                "GET /requests/status.json?command=volume&val=-13 HTTP/1.0",
+            ]
+        );
+    }
+
+    #[test]
+    fn http_password_is_checked() {
+        let vlc = StandIn::start(VlcInterface::Http, |request| {
+            // Basic auth with an empty user and "secret"
+            if request.contains("Authorization: Basic OnNlY3JldA==\r\n") {
+                ok("{}")
+            } else {
+                "HTTP/1.1 401 Unauthorized\r\n\r\n".into()
+            }
+        });
+
+        assert_eq!(
+            vlc.backend("wrong").play(),
+            Err("VLC rejected the HTTP password".into())
+        );
+        assert_eq!(vlc.backend("secret").play(), Ok(()));
+    }
+
+    #[test]
+    fn http_errors_are_reported() {
+        let vlc = StandIn::start(VlcInterface::Http, |request| {
+            if request.contains("pl_next") {
+                "HTTP/1.1 500 Internal Server Error\r\n\r\n".into()
+            } else {
+                "garbage".into()
+            }
+        });
+        let backend = vlc.backend("");
+
+        assert_eq!(
+            backend.perform(MediaAction::Next),
+            Err("VLC responded with HTTP 500".into())
+        );
+        assert_eq!(
+            backend.play(),
+            Err("malformed HTTP response from VLC".into())
+        );
+    }
+
+    #[test]
+    fn rc_answers_are_parsed() {
+        let vlc = StandIn::start(VlcInterface::Rc, |request| {
+            match request.trim() {
+                "is_playing" => "> 1\r\n",
+                "get_time" => "> 73\r\n> ",
+                _ => "> ",
+            }
+            .into()
+        });
+        let backend = vlc.backend("");
+
+        assert_eq!(
+            backend.query().unwrap(),
+            PlaybackState {
+                playing: true,
+                position_ms: Some(73_000),
+            }
+        );
+        backend.pause().unwrap();
+        assert_eq!(
+            vlc.requests(),
+            ["is_playing", "get_time", "is_playing", "pause"]
+        );
+    }
+
+    #[test]
+    fn rc_pause_leaves_a_paused_player_alone() {
+        let vlc = StandIn::start(VlcInterface::Rc, |request| {
+            match request.trim() {
+                "is_playing" => "> 0\r\n",
+                _ => "> ",
+            }
+            .into()
+        });
+        let backend = vlc.backend("");
+
+        backend.pause().unwrap();
+        assert_eq!(vlc.requests(), ["is_playing"]);
+        assert_eq!(
+            backend.rc_number("get_time"),
+            Err("no answer from VLC to get_time".into())
+        );
+    }
+
+    #[test]
+    fn rc_replies_end_at_the_sentinel() {
+        let vlc = StandIn::start(VlcInterface::Rc, |request| {
+            match request.trim() {
+                "is_playing" => "> 1\r\n> ",
+                _ => "> ",
+            }
+            .into()
+        });
+
+        // Without the sentinel each reply would run into the read timeout
+        let started = Instant::now();
+        vlc.backend("").pause().unwrap();
+        assert!(started.elapsed() < IO_TIMEOUT);
+        assert_eq!(vlc.requests(), ["is_playing", "pause"]);
+    }
+
+    #[test]
+    fn http_mute_brings_the_volume_back() {
+        static VOLUME: AtomicI64 = AtomicI64::new(200);
+        let vlc = StandIn::start(VlcInterface::Http, |request| {
+            let query = request.split_whitespace().nth(1).unwrap_or_default();
+            if let Some(volume) = query.strip_prefix("/requests/status.json?command=volume&val=") {
+                VOLUME.store(volume.parse().unwrap(), Ordering::Relaxed);
+            }
+            ok(&format!(
+                r#"{{"volume": {}}}"#,
+                VOLUME.load(Ordering::Relaxed)
+            ))
+        });
+        let backend = vlc.backend("");
+
+        backend.perform(MediaAction::Mute).unwrap();
+        assert_eq!(VOLUME.load(Ordering::Relaxed), 0);
+        backend.perform(MediaAction::Mute).unwrap();
+        assert_eq!(VOLUME.load(Ordering::Relaxed), 200);
+        assert_eq!(
+            vlc.requests(),
+            [
+                "GET /requests/status.json HTTP/1.0",
+                "GET /requests/status.json?command=volume&val=0 HTTP/1.0",
+                "GET /requests/status.json HTTP/1.0",
+                "GET /requests/status.json?command=volume&val=200 HTTP/1.0",
+            ]
+        );
+
+        // Muted before we came along, so the old volume is unknown
+        VOLUME.store(0, Ordering::Relaxed);
+        vlc.backend("").perform(MediaAction::Mute).unwrap();
+        assert_eq!(VOLUME.load(Ordering::Relaxed), DEFAULT_VOLUME);
+    }
+
+    #[test]
+    fn rc_mute_brings_the_volume_back() {
+        static VOLUME: AtomicI64 = AtomicI64::new(320);
+        let vlc = StandIn::start(VlcInterface::Rc, |request| {
+            match request.trim().strip_prefix("volume") {
+                Some("") => format!("> {}\r\n> ", VOLUME.load(Ordering::Relaxed)),
+                Some(volume) => {
+                    VOLUME.store(volume.trim().parse().unwrap(), Ordering::Relaxed);
+                    "> ".into()
+                }
+                None => "> ".into(),
+            }
+        });
+        let backend = vlc.backend("");
+
+        backend.perform(MediaAction::Mute).unwrap();
+        assert_eq!(VOLUME.load(Ordering::Relaxed), 0);
+        backend.perform(MediaAction::Mute).unwrap();
+        assert_eq!(VOLUME.load(Ordering::Relaxed), 320);
+        assert_eq!(
+            vlc.requests(),
+            ["volume", "volume 0", "volume", "volume 320"]
+        );
+    }
+}
@@ -0,0 +1,189 @@
+use std::collections::{HashMap, VecDeque};
+
+use serde::{Deserialize, Serialize};
+
+use crate::protocol::Message;
+
+/// Hard cap on queued messages; the oldest are dropped beyond this.
+const MAX_QUEUED: usize = 100;
+
+/// What to do with a message of a given type while we're offline.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum QueuePolicy {
+    /// Discard it; it's meaningless once stale.
+    Drop,
+    /// Queue every message.
+    Keep,
+    /// Two queued messages of this type cancel out (e.g. toggle + toggle).
+    Coalesce,
+    /// Only the newest message of this type is kept.
+    Latest,
+    /// The newest message replaces any queued message whose type also uses
+    /// `Supersede`, e.g. a pause makes an earlier queued play irrelevant.
+    Supersede,
+}
+
+/// Outgoing messages held while there is no live connection, flushed in
+/// order once the next connection comes up.
+pub struct Outbox {
+    policies: HashMap<String, QueuePolicy>,
+    queue: VecDeque<Message>,
+}
+
+impl Default for Outbox {
+    fn default() -> Self {
+        let policies = [
+            ("ping", QueuePolicy::Drop),
+            ("position", QueuePolicy::Drop),
+            ("toggle", QueuePolicy::Coalesce),
+            ("play", QueuePolicy::Supersede),
+            ("pause", QueuePolicy::Supersede),
+            ("stop", QueuePolicy::Supersede),
+            ("seek", QueuePolicy::Latest),
+            ("mute", QueuePolicy::Coalesce),
+        ]
+        .into_iter()
+        .map(|(msg_type, policy)| (msg_type.to_string(), policy))
+        .collect();
+
+        Self {
+            policies,
+            queue: VecDeque::new(),
+        }
+    }
+}
+
+impl Outbox {
+    pub fn policy(&self, msg_type: &str) -> QueuePolicy {
+        self.policies
+            .get(msg_type)
+            .copied()
+            .unwrap_or(QueuePolicy::Keep)
+    }
+
+    pub fn set_policy(&mut self, msg_type: String, policy: QueuePolicy) {
+        self.policies.insert(msg_type, policy);
+    }
+
+    pub fn push(&mut self, msg: Message) {
+        let msg_type = msg.kind.type_name().to_string();
+
+        match self.policy(&msg_type) {
+            QueuePolicy::Drop => return,
+            QueuePolicy::Keep => {}
+            QueuePolicy::Coalesce => {
+                if let Some(i) = self
+                    .queue
+                    .iter()
+                    .rposition(|m| m.kind.type_name() == msg_type)
+                {
+                    self.queue.remove(i);
+                    return;
+                }
+            }
+            QueuePolicy::Latest => {
+                self.queue.retain(|m| m.kind.type_name() != msg_type);
+            }
+            QueuePolicy::Supersede => {
+                let policies = &self.policies;
+                self.queue
+                    .retain(|m| policies.get(m.kind.type_name()) != Some(&QueuePolicy::Supersede));
+            }
+        }
+
+        self.queue.push_back(msg);
+        if self.queue.len() > MAX_QUEUED {
+            self.queue.pop_front();
+        }
+    }
+
+    pub fn drain(&mut self) -> Vec<Message> {
+        self.queue.drain(..).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn clear(&mut self) {
+        self.queue.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::MessageKind;
+
+    fn queued(outbox: &mut Outbox) -> Vec<String> {
+        outbox
+            .drain()
+            .iter()
+            .map(|m| m.kind.type_name().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn stale_types_are_dropped() {
+        let mut outbox = Outbox::default();
+        outbox.push(Message::ping());
+        outbox.push(Message::new(MessageKind::Next));
+        assert_eq!(queued(&mut outbox), ["next"]);
+    }
+
+    #[test]
+    fn toggles_cancel_out_in_pairs() {
+        let mut outbox = Outbox::default();
+        outbox.push(Message::toggle());
+        outbox.push(Message::new(MessageKind::Next));
+        outbox.push(Message::toggle());
+        outbox.push(Message::toggle());
+        assert_eq!(queued(&mut outbox), ["next", "toggle"]);
+    }
+
+    #[test]
+    fn only_the_latest_seek_is_kept() {
+        let mut outbox = Outbox::default();
+        outbox.push(Message::seek(1_000));
+        outbox.push(Message::toggle());
+        outbox.push(Message::seek(2_000));
+
+        let drained = outbox.drain();
+        assert_eq!(drained.len(), 2);
+        assert!(matches!(
+            drained[1].kind,
+            MessageKind::Seek { position: 2_000 }
+        ));
+    }
+
+    #[test]
+    fn playback_state_supersedes_earlier_state() {
+        let mut outbox = Outbox::default();
+        outbox.push(Message::new(MessageKind::Play));
+        outbox.push(Message::seek(1_000));
+        outbox.push(Message::new(MessageKind::Pause));
+        outbox.push(Message::new(MessageKind::Play));
+        assert_eq!(queued(&mut outbox), ["seek", "play"]);
+    }
+
+    #[test]
+    fn policies_can_be_overridden() {
+        let mut outbox = Outbox::default();
+        outbox.set_policy("toggle".into(), QueuePolicy::Keep);
+        outbox.push(Message::toggle());
+        outbox.push(Message::toggle());
+        assert_eq!(queued(&mut outbox), ["toggle", "toggle"]);
+    }
+
+    #[test]
+    fn oldest_are_dropped_beyond_the_cap() {
+        let mut outbox = Outbox::default();
+        outbox.push(Message::new(MessageKind::Previous));
+        for _ in 0..MAX_QUEUED {
+            outbox.push(Message::new(MessageKind::Next));
+        }
+        assert_eq!(outbox.len(), MAX_QUEUED);
+        assert!(queued(&mut outbox).iter().all(|t| t == "next"));
+    }
+}
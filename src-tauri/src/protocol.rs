@@ -1,49 +1,307 @@
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Protocol version this client speaks.
+pub const PROTOCOL_VERSION: u32 = 1;
+/// Oldest server protocol version we can still talk to.
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
+
+/// Capabilities we advertise in `hello`.
+pub const CLIENT_CAPABILITIES: &[Capability] =
+    &[Capability::Seek, Capability::ClockSync, Capability::Acks];
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Capability {
+    Seek,
+    ClockSync,
+    BinaryEncoding,
+    Acks,
+    /// Anything advertised by the server that this build doesn't know about.
+    #[serde(other)]
+    Unsupported,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(
+    tag = "type",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+pub enum MessageKind {
+    Toggle,
+    Play,
+    Pause,
+    Next,
+    Previous,
+    Stop,
+    VolumeUp,
+    VolumeDown,
+    Mute,
+    Ping,
+    Pong {
+        /// Server clock when the pong was sent, if the server supports clock sync.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        server_time: Option<i64>,
+    },
+    Seek {
+        position: i64,
+    },
+    /// Periodic report of where a client's player is, for drift correction.
+    Position {
+        position: i64,
+        playing: bool,
+    },
+    Hello {
+        version: u32,
+        client: String,
+        capabilities: Vec<Capability>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        display_name: Option<String>,
+    },
+    Welcome {
+        version: u32,
+        capabilities: Vec<Capability>,
+    },
+    /// Server confirmation that our control message `seq` was relayed.
+    Ack {
+        seq: u64,
+    },
+    /// Snapshot of the room sent by the server right after we join.
+    RoomState(RoomSnapshot),
+    /// Full member list, sent on join.
+    Members {
+        members: Vec<Member>,
+    },
+    MemberJoined {
+        member: Member,
+    },
+    MemberLeft {
+        member_id: String,
+    },
+    /// Any message whose type we don't recognise (or whose payload doesn't
+    /// match the known shape). Holds the raw JSON as received.
+    #[serde(skip)]
+    Unknown(Value),
+}
+
+#[derive(Debug, Clone, Serialize)]
 pub struct Message {
-    #[serde(rename = "type")]
-    pub msg_type: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub position: Option<i64>,
+    #[serde(flatten)]
+    pub kind: MessageKind,
     pub timestamp: i64,
     #[serde(rename = "clientId", skip_serializing_if = "Option::is_none")]
     pub client_id: Option<String>,
+    /// Server-clock instant at which every client should apply the command.
+    #[serde(rename = "executeAt", skip_serializing_if = "Option::is_none")]
+    pub execute_at: Option<i64>,
+    /// Per-client sequence number, set on control messages.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seq: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct Envelope {
+    #[serde(default)]
+    timestamp: i64,
+    #[serde(rename = "clientId", default)]
+    client_id: Option<String>,
+    #[serde(rename = "executeAt", default)]
+    execute_at: Option<i64>,
+    #[serde(default)]
+    seq: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct RoomSnapshot {
+    pub playing: bool,
+    /// Playback position in ms as of the message timestamp.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub position: Option<i64>,
+    /// Client that sent the last playback command.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_actor: Option<String>,
+    #[serde(default)]
+    pub member_count: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Member {
+    pub client_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+    /// Local time we last heard from or about this member.
+    #[serde(default)]
+    pub last_seen: i64,
+}
+
+impl RoomSnapshot {
+    /// Folds a playback command into the snapshot so it stays current
+    /// after the initial join.
+    pub fn apply(&mut self, msg: &Message) {
+        match msg.kind {
+            MessageKind::Toggle => self.playing = !self.playing,
+            MessageKind::Play => self.playing = true,
+            MessageKind::Pause => self.playing = false,
+            MessageKind::Stop => {
+                self.playing = false;
+                self.position = None;
+            }
+            // A different track; we no longer know where it is
+            MessageKind::Next | MessageKind::Previous => self.position = None,
+            MessageKind::Seek { position } => self.position = Some(position),
+            _ => return,
+        }
+        if msg.client_id.is_some() {
+            self.last_actor = msg.client_id.clone();
+        }
+    }
+}
+
+impl MessageKind {
+    /// The `type` tag this kind is sent with.
+    pub fn type_name(&self) -> &str {
+        match self {
+            Self::Toggle => "toggle",
+            Self::Play => "play",
+            Self::Pause => "pause",
+            Self::Next => "next",
+            Self::Previous => "previous",
+            Self::Stop => "stop",
+            Self::VolumeUp => "volumeUp",
+            Self::VolumeDown => "volumeDown",
+            Self::Mute => "mute",
+            Self::Ping => "ping",
+            Self::Pong { .. } => "pong",
+            Self::Seek { .. } => "seek",
+            Self::Position { .. } => "position",
+            Self::Hello { .. } => "hello",
+            Self::Welcome { .. } => "welcome",
+            Self::Ack { .. } => "ack",
+            Self::RoomState(_) => "roomState",
+            Self::Members { .. } => "members",
+            Self::MemberJoined { .. } => "memberJoined",
+            Self::MemberLeft { .. } => "memberLeft",
+            Self::Unknown(raw) => raw.get("type").and_then(Value::as_str).unwrap_or(""),
+        }
+    }
+
+    /// Playback commands that must be delivered exactly once.
+    pub fn is_control(&self) -> bool {
+        matches!(
+            self,
+            Self::Toggle
+                | Self::Play
+                | Self::Pause
+                | Self::Next
+                | Self::Previous
+                | Self::Stop
+                | Self::VolumeUp
+                | Self::VolumeDown
+                | Self::Mute
+                | Self::Seek { .. }
+        )
+    }
+
+    /// Messages only room members may read or send: playback commands and
+    /// position reports, which can make other clients seek.
+    pub fn is_private(&self) -> bool {
+        self.is_control() || matches!(self, Self::Position { .. })
+    }
+}
+
+impl<'de> Deserialize<'de> for Message {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+
+        let raw = Value::deserialize(deserializer)?;
+        let envelope = Envelope::deserialize(&raw).map_err(D::Error::custom)?;
+        let kind = MessageKind::deserialize(&raw).unwrap_or(MessageKind::Unknown(raw));
+
+        Ok(Self {
+            kind,
+            timestamp: envelope.timestamp,
+            client_id: envelope.client_id,
+            execute_at: envelope.execute_at,
+            seq: envelope.seq,
+        })
+    }
 }
 
 impl Message {
-    pub fn toggle() -> Self {
+    pub fn new(kind: MessageKind) -> Self {
         Self {
-            msg_type: "toggle".into(),
-            position: None,
+            kind,
             timestamp: now_ms(),
             client_id: None,
+            execute_at: None,
+            seq: None,
         }
     }
 
+    pub fn with_execute_at(mut self, execute_at: Option<i64>) -> Self {
+        self.execute_at = execute_at;
+        self
+    }
+
+    pub fn toggle() -> Self {
+        Self::new(MessageKind::Toggle)
+    }
+
     pub fn ping() -> Self {
-        Self {
-            msg_type: "ping".into(),
-            position: None,
-            timestamp: now_ms(),
-            client_id: None,
-        }
+        Self::new(MessageKind::Ping)
     }
 
-    pub fn is_toggle(&self) -> bool {
-        self.msg_type == "toggle"
+    pub fn seek(position_ms: i64) -> Self {
+        Self::new(MessageKind::Seek {
+            position: position_ms,
+        })
     }
 
-    pub fn is_play(&self) -> bool {
-        self.msg_type == "play"
+    pub fn hello(display_name: Option<String>) -> Self {
+        Self::new(MessageKind::Hello {
+            version: PROTOCOL_VERSION,
+            client: format!("{}/{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")),
+            capabilities: CLIENT_CAPABILITIES.to_vec(),
+            display_name,
+        })
     }
+}
+
+/// Result of the hello/welcome exchange.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct Negotiated {
+    pub version: u32,
+    pub capabilities: Vec<Capability>,
+}
+
+impl Negotiated {
+    /// Checks the server's welcome against what we support. Returns the
+    /// reason as an error if the versions can't interoperate.
+    pub fn from_welcome(version: u32, server_capabilities: &[Capability]) -> Result<Self, String> {
+        if !(MIN_PROTOCOL_VERSION..=PROTOCOL_VERSION).contains(&version) {
+            return Err(format!(
+                "server speaks protocol v{}, client supports v{}-v{}",
+                version, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION
+            ));
+        }
+
+        let capabilities = CLIENT_CAPABILITIES
+            .iter()
+            .filter(|c| server_capabilities.contains(c))
+            .copied()
+            .collect();
 
-    pub fn is_pause(&self) -> bool {
-        self.msg_type == "pause"
+        Ok(Self {
+            version,
+            capabilities,
+        })
     }
 
-    pub fn is_pong(&self) -> bool {
-        self.msg_type == "pong"
+    pub fn supports(&self, capability: Capability) -> bool {
+        self.capabilities.contains(&capability)
     }
 }
 
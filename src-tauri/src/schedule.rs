@@ -0,0 +1,50 @@
+use std::time::Duration;
+
+use tracing::warn;
+
+use crate::protocol;
+use crate::state::AppState;
+
+const MIN_LEAD_MS: i64 = 150;
+const MAX_LEAD_MS: i64 = 1_500;
+
+/// Longest we'll wait on a deadline. No client schedules further ahead than
+/// `MAX_LEAD_MS`; the slack covers error in the clock offset estimate.
+const MAX_DELAY_MS: i64 = MAX_LEAD_MS + 1_000;
+
+/// Picks a server-clock deadline for a command we're about to send, far
+/// enough ahead that it reaches the rest of the room before it fires.
+/// Returns `None` until we have a clock offset estimate to schedule against.
+pub fn deadline(state: &AppState) -> Option<i64> {
+    let clock = state.clock.read();
+    clock.offset_ms()?;
+    let lead = (clock.min_rtt_ms()? * 2).clamp(MIN_LEAD_MS, MAX_LEAD_MS);
+    Some(clock.to_server(protocol::now_ms() + lead))
+}
+
+/// Runs `action` when the server clock reaches `execute_at`, or right away
+/// if there is no deadline or it has already passed. Always runs on a
+/// blocking thread, since backends talk to players synchronously and
+/// callers include the WebSocket loop and the hotkey handler.
+pub fn run_at<F>(state: &AppState, execute_at: Option<i64>, action: F)
+where
+    F: FnOnce() + Send + 'static,
+{
+    let mut delay_ms = execute_at
+        .map(|at| state.to_local_time(at) - protocol::now_ms())
+        .unwrap_or(0);
+    if delay_ms > MAX_DELAY_MS {
+        warn!(
+            "Deadline is {}ms away, beyond any lead a client picks; acting now",
+            delay_ms
+        );
+        delay_ms = 0;
+    }
+
+    tauri::async_runtime::spawn_blocking(move || {
+        if delay_ms > 0 {
+            std::thread::sleep(Duration::from_millis(delay_ms as u64));
+        }
+        action();
+    });
+}
@@ -1,14 +1,36 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
 use parking_lot::RwLock;
 use serde::Serialize;
 use tokio::sync::mpsc::UnboundedSender;
+use tokio::sync::Semaphore;
+use tracing::warn;
 
-use crate::protocol::Message;
+use crate::auth::{RoomCredentials, SigningKey};
+use crate::bridge::Bridge;
+use crate::clock::ClockSync;
+use crate::crypto::RoomKey;
+use crate::delivery::{Outbound, SeenSeqs};
+use crate::drift::{DriftSettings, PeerPosition};
+use crate::echo::Burst;
+use crate::hooks::HookSettings;
+use crate::identity::Identity;
+#[cfg(test)]
+use crate::media::RecordingBackend;
+use crate::media::{self, BackendKind, MediaBackend, MediaSettings, MediaTarget};
+use crate::outbox::{Outbox, QueuePolicy};
+use crate::protocol::{self, Capability, Member, Message, Negotiated, RoomSnapshot};
 
 #[derive(Debug, Clone, Serialize, PartialEq)]
 #[serde(tag = "status", rename_all = "lowercase")]
 pub enum ConnectionStatus {
     Disconnected,
-    Connecting,
+    Connecting {
+        queued: usize,
+    },
     Connected {
         room: String,
         #[serde(rename = "latencyMs")]
@@ -16,6 +38,17 @@ pub enum ConnectionStatus {
     },
     Reconnecting {
         attempt: u32,
+        queued: usize,
+    },
+    /// The server rejected our protocol version or spoke one we can't use.
+    /// The connection loop stops instead of retrying.
+    Incompatible {
+        reason: String,
+    },
+    /// The server refused our room credentials. Also stops the loop.
+    #[serde(rename = "authfailed")]
+    AuthFailed {
+        reason: String,
     },
 }
 
@@ -25,24 +58,131 @@ impl Default for ConnectionStatus {
     }
 }
 
+/// Built backends for the enabled media targets, in target order.
+type ActiveMedia = Vec<(MediaTarget, Arc<dyn MediaBackend>)>;
+
 pub struct AppState {
+    pub data_dir: RwLock<Option<PathBuf>>,
+    pub identity: RwLock<Option<Identity>>,
     pub ws_sender: RwLock<Option<UnboundedSender<Message>>>,
     pub connection_status: RwLock<ConnectionStatus>,
     pub current_room: RwLock<Option<String>>,
     pub current_server: RwLock<Option<String>>,
+    pub credentials: RwLock<RoomCredentials>,
+    pub room_key: RwLock<Option<RoomKey>>,
+    pub signing_key: RwLock<Option<SigningKey>>,
     pub last_ping_time: RwLock<Option<i64>>,
+    pub negotiated: RwLock<Option<Negotiated>>,
+    pub clock: RwLock<ClockSync>,
+    pub outbound: RwLock<Outbound>,
+    pub seen_seqs: RwLock<SeenSeqs>,
+    pub outbox: RwLock<Outbox>,
+    pub room_state: RwLock<Option<RoomSnapshot>>,
+    pub align_on_join: RwLock<bool>,
+    pub members: RwLock<HashMap<String, Member>>,
     pub simulate_cooldown_until: RwLock<i64>,
+    pub toggle_burst: RwLock<Option<Burst>>,
+    pub media_settings: RwLock<MediaSettings>,
+    pub media_targets: RwLock<Vec<MediaTarget>>,
+    pub media: RwLock<ActiveMedia>,
+    #[cfg(test)]
+    pub recorder: Arc<RecordingBackend>,
+    pub bridge: Arc<Bridge>,
+    pub last_media_command: RwLock<i64>,
+    pub watch_stop: RwLock<Option<Arc<AtomicBool>>>,
+    pub drift_settings: RwLock<DriftSettings>,
+    pub peer_positions: RwLock<HashMap<String, PeerPosition>>,
+    pub hook_settings: RwLock<HookSettings>,
+    /// Bounds how many hooks run at once; replaced when the limit changes.
+    pub hook_slots: RwLock<Arc<Semaphore>>,
 }
 
 impl AppState {
     pub fn new() -> Self {
-        Self {
+        let state = Self {
+            data_dir: RwLock::new(None),
+            identity: RwLock::new(None),
             ws_sender: RwLock::new(None),
             connection_status: RwLock::new(ConnectionStatus::Disconnected),
             current_room: RwLock::new(None),
             current_server: RwLock::new(None),
+            credentials: RwLock::new(RoomCredentials::default()),
+            room_key: RwLock::new(None),
+            signing_key: RwLock::new(None),
             last_ping_time: RwLock::new(None),
+            negotiated: RwLock::new(None),
+            clock: RwLock::new(ClockSync::default()),
+            outbound: RwLock::new(Outbound::default()),
+            seen_seqs: RwLock::new(SeenSeqs::default()),
+            outbox: RwLock::new(Outbox::default()),
+            room_state: RwLock::new(None),
+            align_on_join: RwLock::new(false),
+            members: RwLock::new(HashMap::new()),
             simulate_cooldown_until: RwLock::new(0),
+            toggle_burst: RwLock::new(None),
+            media_settings: RwLock::new(MediaSettings::default()),
+            media_targets: RwLock::new(vec![MediaTarget::new(BackendKind::default())]),
+            media: RwLock::new(Vec::new()),
+            #[cfg(test)]
+            recorder: Arc::new(RecordingBackend::default()),
+            bridge: Arc::new(Bridge::default()),
+            last_media_command: RwLock::new(0),
+            watch_stop: RwLock::new(None),
+            drift_settings: RwLock::new(DriftSettings::default()),
+            peer_positions: RwLock::new(HashMap::new()),
+            hook_slots: RwLock::new(Arc::new(Semaphore::new(
+                HookSettings::default().max_concurrent,
+            ))),
+            hook_settings: RwLock::new(HookSettings::default()),
+        };
+        let media = state
+            .build_media(&state.media_targets(), &MediaSettings::default())
+            .unwrap_or_default();
+        *state.media.write() = media;
+        state
+    }
+
+    /// Loads (or creates) the persistent identity stored in `data_dir`.
+    pub fn init_identity(&self, data_dir: PathBuf) {
+        *self.identity.write() = Some(Identity::load_or_create(&data_dir));
+        *self.data_dir.write() = Some(data_dir);
+    }
+
+    pub fn get_identity(&self) -> Option<Identity> {
+        self.identity.read().clone()
+    }
+
+    pub fn client_id(&self) -> Option<String> {
+        self.identity.read().as_ref().map(|i| i.client_id.clone())
+    }
+
+    pub fn display_name(&self) -> Option<String> {
+        self.identity
+            .read()
+            .as_ref()
+            .and_then(|i| i.display_name.clone())
+    }
+
+    pub fn set_display_name(&self, name: Option<String>) -> Result<(), String> {
+        let mut guard = self.identity.write();
+        let identity = guard.as_mut().ok_or("Identity not loaded")?;
+        identity.display_name = name.filter(|n| !n.trim().is_empty());
+
+        match self.data_dir.read().as_ref() {
+            Some(dir) => identity.save(dir),
+            None => Ok(()),
+        }
+    }
+
+    /// Whether `msg` was sent by this client.
+    pub fn is_own(&self, msg: &Message) -> bool {
+        msg.client_id.is_some() && msg.client_id == self.client_id()
+    }
+
+    /// Stamps our client ID on an outgoing message.
+    pub fn stamp(&self, msg: &mut Message) {
+        if msg.client_id.is_none() {
+            msg.client_id = self.client_id();
         }
     }
 
@@ -58,7 +198,29 @@ impl AppState {
         *self.ws_sender.write() = sender;
     }
 
-    pub fn send_message(&self, msg: Message) -> Result<(), String> {
+    pub fn send_message(&self, mut msg: Message) -> Result<(), String> {
+        // Stamp in server time so other clients can map it onto their own clocks
+        msg.timestamp = self.to_server_time(msg.timestamp);
+
+        if self.ws_sender.read().is_none() {
+            return Err("Not connected".into());
+        }
+
+        // Hold it until the connection is back up rather than losing it
+        if !matches!(self.get_status(), ConnectionStatus::Connected { .. }) {
+            self.queue_message(msg);
+            return Ok(());
+        }
+
+        self.dispatch(msg)
+    }
+
+    fn dispatch(&self, mut msg: Message) -> Result<(), String> {
+        // Re-sent messages keep the sequence number they were first given
+        if msg.kind.is_control() && msg.seq.is_none() {
+            self.outbound.write().track(&mut msg);
+        }
+
         let guard = self.ws_sender.read();
         if let Some(sender) = guard.as_ref() {
             sender.send(msg).map_err(|e| e.to_string())
@@ -67,6 +229,64 @@ impl AppState {
         }
     }
 
+    /// Adds a message to the outbox according to its type's queue policy.
+    pub fn queue_message(&self, msg: Message) {
+        self.outbox.write().push(msg);
+
+        let queued_now = self.outbox.read().len();
+        match &mut *self.connection_status.write() {
+            ConnectionStatus::Connecting { queued }
+            | ConnectionStatus::Reconnecting { queued, .. } => *queued = queued_now,
+            _ => {}
+        }
+    }
+
+    pub fn queued_count(&self) -> usize {
+        self.outbox.read().len()
+    }
+
+    /// Sends everything queued while offline, in order.
+    pub fn flush_outbox(&self) {
+        let queued = self.outbox.write().drain();
+        for msg in queued {
+            if let Err(e) = self.dispatch(msg) {
+                warn!("Failed to flush queued message: {}", e);
+            }
+        }
+    }
+
+    pub fn clear_outbox(&self) {
+        self.outbox.write().clear();
+    }
+
+    pub fn set_queue_policy(&self, msg_type: String, policy: QueuePolicy) {
+        self.outbox.write().set_policy(msg_type, policy);
+    }
+
+    pub fn ack(&self, seq: u64) -> bool {
+        self.outbound.write().ack(seq)
+    }
+
+    /// Queues every unacked control message for sending again, keeping its
+    /// original sequence number so receivers can drop it if already applied.
+    pub fn resend_unacked(&self) {
+        let pending = self.outbound.read().unacked();
+        if let Some(sender) = self.ws_sender.read().as_ref() {
+            for msg in pending {
+                let _ = sender.send(msg);
+            }
+        }
+    }
+
+    pub fn clear_unacked(&self) {
+        self.outbound.write().clear();
+    }
+
+    /// Returns `false` if this `(clientId, seq)` was already applied.
+    pub fn mark_seen(&self, client_id: &str, seq: u64) -> bool {
+        self.seen_seqs.write().insert(client_id, seq)
+    }
+
     pub fn set_room(&self, room: Option<String>) {
         *self.current_room.write() = room;
     }
@@ -75,6 +295,32 @@ impl AppState {
         *self.current_server.write() = server;
     }
 
+    /// Stores the room credentials, deriving the signing key and, if
+    /// enabled, the end-to-end key.
+    pub fn set_credentials(&self, credentials: RoomCredentials, room: &str) {
+        *self.signing_key.write() = credentials
+            .passphrase
+            .as_deref()
+            .map(|passphrase| SigningKey::derive(passphrase, room));
+        *self.room_key.write() = match &credentials.passphrase {
+            Some(passphrase) if credentials.encrypt => Some(RoomKey::derive(passphrase, room)),
+            _ => None,
+        };
+        *self.credentials.write() = credentials;
+    }
+
+    pub fn token(&self) -> Option<String> {
+        self.credentials.read().token.clone()
+    }
+
+    pub fn signing_key(&self) -> Option<SigningKey> {
+        self.signing_key.read().clone()
+    }
+
+    pub fn room_key(&self) -> Option<RoomKey> {
+        self.room_key.read().clone()
+    }
+
     pub fn get_room(&self) -> Option<String> {
         self.current_room.read().clone()
     }
@@ -87,7 +333,271 @@ impl AppState {
         self.last_ping_time.write().take()
     }
 
+    pub fn set_negotiated(&self, negotiated: Option<Negotiated>) {
+        *self.negotiated.write() = negotiated;
+    }
+
+    pub fn supports(&self, capability: Capability) -> bool {
+        self.negotiated
+            .read()
+            .as_ref()
+            .is_some_and(|n| n.supports(capability))
+    }
+
+    pub fn record_clock_sample(&self, sent_at: i64, server_time: i64, received_at: i64) {
+        self.clock
+            .write()
+            .add_sample(sent_at, server_time, received_at);
+    }
+
+    pub fn clock_offset(&self) -> Option<i64> {
+        self.clock.read().offset_ms()
+    }
+
+    pub fn to_local_time(&self, server_time: i64) -> i64 {
+        self.clock.read().to_local(server_time)
+    }
+
+    pub fn to_server_time(&self, local_time: i64) -> i64 {
+        self.clock.read().to_server(local_time)
+    }
+
+    pub fn reset_clock(&self) {
+        self.clock.write().reset();
+    }
+
+    pub fn set_room_state(&self, snapshot: Option<RoomSnapshot>) {
+        *self.room_state.write() = snapshot;
+    }
+
+    pub fn get_room_state(&self) -> Option<RoomSnapshot> {
+        self.room_state.read().clone()
+    }
+
+    /// Updates the stored snapshot from a playback command. Returns the new
+    /// snapshot if one exists and the command changed it.
+    pub fn update_room_state(&self, msg: &Message) -> Option<RoomSnapshot> {
+        let mut guard = self.room_state.write();
+        let snapshot = guard.as_mut()?;
+        let before = snapshot.clone();
+        snapshot.apply(msg);
+        (*snapshot != before).then(|| snapshot.clone())
+    }
+
+    pub fn set_align_on_join(&self, enabled: bool) {
+        *self.align_on_join.write() = enabled;
+    }
+
+    pub fn align_on_join(&self) -> bool {
+        *self.align_on_join.read()
+    }
+
+    pub fn set_members(&self, members: Vec<Member>) {
+        let now = protocol::now_ms();
+        let mut registry = self.members.write();
+        registry.clear();
+        for mut member in members {
+            member.last_seen = now;
+            registry.insert(member.client_id.clone(), member);
+        }
+        drop(registry);
+        self.sync_member_count();
+    }
+
+    pub fn add_member(&self, mut member: Member) -> Member {
+        member.last_seen = protocol::now_ms();
+        self.members
+            .write()
+            .insert(member.client_id.clone(), member.clone());
+        self.sync_member_count();
+        member
+    }
+
+    pub fn remove_member(&self, client_id: &str) -> Option<Member> {
+        let removed = self.members.write().remove(client_id);
+        self.peer_positions.write().remove(client_id);
+        self.sync_member_count();
+        removed
+    }
+
+    /// Records that we just heard from `client_id`.
+    pub fn touch_member(&self, client_id: &str) {
+        if let Some(member) = self.members.write().get_mut(client_id) {
+            member.last_seen = protocol::now_ms();
+        }
+    }
+
+    /// Members sorted by display name, falling back to client ID.
+    pub fn get_members(&self) -> Vec<Member> {
+        let mut members: Vec<Member> = self.members.read().values().cloned().collect();
+        members.sort_by(|a, b| {
+            let a = a.display_name.as_deref().unwrap_or(&a.client_id);
+            let b = b.display_name.as_deref().unwrap_or(&b.client_id);
+            a.cmp(b)
+        });
+        members
+    }
+
+    pub fn clear_members(&self) {
+        self.members.write().clear();
+        self.peer_positions.write().clear();
+    }
+
+    fn sync_member_count(&self) {
+        let count = self.members.read().len() as u32;
+        if let Some(snapshot) = self.room_state.write().as_mut() {
+            snapshot.member_count = count;
+        }
+    }
+
     pub fn set_cooldown(&self, until: i64) {
         *self.simulate_cooldown_until.write() = until;
     }
+
+    /// Milliseconds left before we may simulate the media key again.
+    pub fn cooldown_remaining(&self) -> i64 {
+        (*self.simulate_cooldown_until.read() - protocol::now_ms()).max(0)
+    }
+
+    /// Builds a backend for each enabled target.
+    fn build_media(
+        &self,
+        targets: &[MediaTarget],
+        settings: &MediaSettings,
+    ) -> Result<ActiveMedia, String> {
+        targets
+            .iter()
+            .filter(|target| target.enabled)
+            .map(|target| {
+                #[cfg(test)]
+                if target.kind == BackendKind::Recording {
+                    let recorder: Arc<dyn MediaBackend> = self.recorder.clone();
+                    return Ok((target.clone(), recorder));
+                }
+                media::create(target.kind, settings, &self.bridge)
+                    .map(|backend| (target.clone(), backend))
+                    .map_err(|e| format!("{:?} backend: {}", target.kind, e))
+            })
+            .collect()
+    }
+
+    /// Updates backend configuration, rebuilding the active backends with it.
+    pub fn set_media_settings(&self, settings: MediaSettings) -> Result<(), String> {
+        let media = self.build_media(&self.media_targets(), &settings)?;
+        *self.media_settings.write() = settings;
+        *self.media.write() = media;
+        Ok(())
+    }
+
+    pub fn media_settings(&self) -> MediaSettings {
+        self.media_settings.read().clone()
+    }
+
+    /// Replaces the set of backends commands fan out to. Nothing changes if
+    /// any enabled backend can't be built.
+    pub fn set_media_targets(&self, targets: Vec<MediaTarget>) -> Result<(), String> {
+        let media = self.build_media(&targets, &self.media_settings())?;
+        *self.media_targets.write() = targets;
+        *self.media.write() = media;
+        Ok(())
+    }
+
+    pub fn media_targets(&self) -> Vec<MediaTarget> {
+        self.media_targets.read().clone()
+    }
+
+    /// Enabled backends that react to `msg_type`.
+    pub fn media_for(&self, msg_type: &str) -> Vec<Arc<dyn MediaBackend>> {
+        self.media
+            .read()
+            .iter()
+            .filter(|(target, _)| target.accepts(msg_type))
+            .map(|(_, backend)| backend.clone())
+            .collect()
+    }
+
+    /// Every enabled backend.
+    pub fn all_media(&self) -> Vec<Arc<dyn MediaBackend>> {
+        self.media.read().iter().map(|(_, b)| b.clone()).collect()
+    }
+
+    /// The first enabled backend, which answers for "the player" where a
+    /// single answer is needed, e.g. the local position.
+    pub fn primary_media(&self) -> Option<Arc<dyn MediaBackend>> {
+        self.media.read().first().map(|(_, b)| b.clone())
+    }
+
+    pub fn has_media(&self, kind: BackendKind) -> bool {
+        self.media
+            .read()
+            .iter()
+            .any(|(target, _)| target.kind == kind)
+    }
+
+    /// Records that we just drove the local player, so the watcher can
+    /// tell our own changes from the user's.
+    pub fn note_media_command(&self) {
+        *self.last_media_command.write() = protocol::now_ms();
+    }
+
+    pub fn since_media_command(&self) -> i64 {
+        protocol::now_ms() - *self.last_media_command.read()
+    }
+
+    /// Replaces the player watcher's stop flag, stopping the previous one.
+    pub fn replace_watch(&self, stop: Arc<AtomicBool>) {
+        if let Some(previous) = self.watch_stop.write().replace(stop) {
+            previous.store(true, Ordering::Relaxed);
+        }
+    }
+
+    pub fn set_drift_settings(&self, settings: DriftSettings) {
+        *self.drift_settings.write() = settings;
+    }
+
+    pub fn drift_settings(&self) -> DriftSettings {
+        self.drift_settings.read().clone()
+    }
+
+    /// Hooks already running keep their slot in the old limit until they
+    /// finish.
+    pub fn set_hook_settings(&self, settings: HookSettings) {
+        if settings.max_concurrent != self.hook_settings.read().max_concurrent {
+            *self.hook_slots.write() = Arc::new(Semaphore::new(settings.max_concurrent));
+        }
+        *self.hook_settings.write() = settings;
+    }
+
+    pub fn hook_settings(&self) -> HookSettings {
+        self.hook_settings.read().clone()
+    }
+
+    pub fn hook_slots(&self) -> Arc<Semaphore> {
+        self.hook_slots.read().clone()
+    }
+
+    pub fn record_peer_position(
+        &self,
+        client_id: &str,
+        position_ms: i64,
+        playing: bool,
+        reported_at: i64,
+    ) {
+        self.peer_positions.write().insert(
+            client_id.to_string(),
+            PeerPosition {
+                position_ms,
+                playing,
+                reported_at,
+            },
+        );
+    }
+
+    pub fn peer_positions(&self) -> Vec<(String, PeerPosition)> {
+        self.peer_positions
+            .read()
+            .iter()
+            .map(|(id, p)| (id.clone(), *p))
+            .collect()
+    }
 }
@@ -2,10 +2,11 @@ use tauri::{
     image::Image,
     menu::{Menu, MenuItem},
     tray::{TrayIcon, TrayIconBuilder},
-    AppHandle, Manager,
+    AppHandle, Manager, Runtime,
 };
 use tracing::error;
 
+use crate::protocol::Member;
 use crate::state::ConnectionStatus;
 
 const TRAY_ID: &str = "main-tray";
@@ -60,9 +61,12 @@ pub fn create(app: &AppHandle) -> Result<TrayIcon, tauri::Error> {
 pub fn update_icon(app: &AppHandle, status: &ConnectionStatus) {
     let icon_name = match status {
         ConnectionStatus::Disconnected => "disconnected",
-        ConnectionStatus::Connecting => "connecting",
+        ConnectionStatus::Connecting { .. } => "connecting",
         ConnectionStatus::Connected { .. } => "connected",
         ConnectionStatus::Reconnecting { .. } => "connecting",
+        ConnectionStatus::Incompatible { .. } | ConnectionStatus::AuthFailed { .. } => {
+            "disconnected"
+        }
     };
 
     if let Some(tray) = app.tray_by_id(TRAY_ID) {
@@ -73,6 +77,25 @@ pub fn update_icon(app: &AppHandle, status: &ConnectionStatus) {
     }
 }
 
+/// Shows who else is in the room in the tray tooltip.
+pub fn update_members<R: Runtime>(app: &AppHandle<R>, members: &[Member]) {
+    let tooltip = if members.is_empty() {
+        None
+    } else {
+        let names: Vec<&str> = members
+            .iter()
+            .map(|m| m.display_name.as_deref().unwrap_or(&m.client_id))
+            .collect();
+        Some(format!("{} watching: {}", members.len(), names.join(", ")))
+    };
+
+    if let Some(tray) = app.tray_by_id(TRAY_ID)
+        && let Err(e) = tray.set_tooltip(tooltip)
+    {
+        error!("Failed to set tray tooltip: {}", e);
+    }
+}
+
 fn load_icon(name: &str) -> Image<'static> {
     // Create a simple colored icon
     let color: [u8; 4] = match name {
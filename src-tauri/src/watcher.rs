@@ -0,0 +1,87 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use tauri::{AppHandle, Runtime};
+use tracing::{debug, info};
+
+use crate::media::PlayerChange;
+use crate::protocol::{Capability, Message, MessageKind};
+use crate::state::AppState;
+use crate::{schedule, websocket};
+
+/// How long after we drive the player ourselves to ignore what it reports.
+/// Players signal changes asynchronously, so this is longer than the
+/// simulate cooldown.
+const SELF_CHANGE_WINDOW_MS: i64 = 1000;
+
+/// Wait before watching again when the player went away or couldn't be
+/// reached, e.g. because it isn't running yet.
+const RETRY_DELAY: Duration = Duration::from_secs(2);
+
+/// (Re)starts watching every enabled backend's player for changes the
+/// user makes there. Call whenever the backends or their settings change.
+pub fn restart(app: &AppHandle, state: &Arc<AppState>) {
+    let stop = Arc::new(AtomicBool::new(false));
+    state.replace_watch(stop.clone());
+
+    for backend in state.all_media() {
+        if !backend.can_watch() {
+            debug!("{} backend cannot be watched", backend.name());
+            continue;
+        }
+
+        let (app, state, stop) = (app.clone(), state.clone(), stop.clone());
+        std::thread::spawn(move || {
+            info!("Watching {} player for local changes", backend.name());
+            let on_change = |change| report(&app, &state, change);
+            while !stop.load(Ordering::Relaxed) {
+                if let Err(e) = backend.watch(&on_change, &stop) {
+                    debug!("Watching {} player failed: {}", backend.name(), e);
+                }
+                if !stop.load(Ordering::Relaxed) {
+                    std::thread::sleep(RETRY_DELAY);
+                }
+            }
+            debug!("Stopped watching {} player", backend.name());
+        });
+    }
+}
+
+/// Tells the room about a change the user made in the local player.
+/// Changes we caused ourselves, and ones the room already agrees with,
+/// are dropped.
+pub fn report<R: Runtime>(app: &AppHandle<R>, state: &AppState, change: PlayerChange) {
+    if state.since_media_command() < SELF_CHANGE_WINDOW_MS {
+        debug!("Ignoring local {:?}, most likely our own doing", change);
+        return;
+    }
+
+    let room_playing = state.get_room_state().map(|room| room.playing);
+    let kind = match change {
+        PlayerChange::Played if room_playing == Some(true) => return,
+        PlayerChange::Paused if room_playing == Some(false) => return,
+        PlayerChange::Played => MessageKind::Play,
+        PlayerChange::Paused => MessageKind::Pause,
+        PlayerChange::Seeked { position_ms } => {
+            if !state.supports(Capability::Seek) {
+                return;
+            }
+            MessageKind::Seek {
+                position: position_ms,
+            }
+        }
+    };
+
+    let execute_at = schedule::deadline(state);
+    let msg = Message::new(kind.clone()).with_execute_at(execute_at);
+    if let Err(e) = websocket::send(app, state, msg) {
+        debug!("Not broadcasting local {:?}: {}", change, e);
+        return;
+    }
+    info!("Broadcasting local {:?}", change);
+
+    let mut msg = Message::new(kind);
+    state.stamp(&mut msg);
+    websocket::emit_sync_event(app, state, &msg);
+}
@@ -1,35 +1,59 @@
 use std::sync::Arc;
 use std::time::Duration;
 
+use futures_util::stream::{SplitSink, SplitStream};
 use futures_util::{SinkExt, StreamExt};
-use tauri::{AppHandle, Emitter};
+use serde::Serialize;
+use serde_json::Value;
+use tauri::{AppHandle, Emitter, Runtime};
+use tokio::net::TcpStream;
 use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
-use tokio_tungstenite::{connect_async, tungstenite::Message as WsMessage};
-use tracing::{error, info, warn};
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::http::{header, HeaderValue, StatusCode};
+use tokio_tungstenite::tungstenite::{self, Message as WsMessage};
+use tokio_tungstenite::{connect_async, MaybeTlsStream, WebSocketStream};
+use tracing::{debug, error, info, warn};
 use url::Url;
 
-use crate::protocol::{self, Message};
+use crate::auth::{self, RoomCredentials};
+use crate::crypto::{self, DecryptError};
+use crate::echo::{self, SuppressReason};
+use crate::hooks::{self, Direction};
+use crate::protocol::{self, Capability, Message, MessageKind, Negotiated, RoomSnapshot};
 use crate::state::{AppState, ConnectionStatus};
-use crate::tray;
+use crate::{drift, media, schedule, tray};
 
 const MAX_BACKOFF_MS: u64 = 30_000;
 const INITIAL_BACKOFF_MS: u64 = 1_000;
 const PING_INTERVAL_MS: u64 = 5_000;
+const HANDSHAKE_TIMEOUT_MS: u64 = 5_000;
+
+type WsStream = WebSocketStream<MaybeTlsStream<TcpStream>>;
+type WsWrite = SplitSink<WsStream, WsMessage>;
+type WsRead = SplitStream<WsStream>;
 
 pub async fn connect(
     app: AppHandle,
     state: Arc<AppState>,
     server_url: String,
     room: String,
+    credentials: RoomCredentials,
 ) -> Result<(), String> {
     // Disconnect existing connection
     state.set_sender(None);
+    state.reset_clock();
+    state.clear_unacked();
+    state.seen_seqs.write().clear();
+    state.set_room_state(None);
+    state.clear_members();
 
     state.set_server(Some(server_url.clone()));
     state.set_room(Some(room.clone()));
-    state.set_status(ConnectionStatus::Connecting);
+    state.set_credentials(credentials, &room);
+    state.clear_outbox();
+    state.set_status(ConnectionStatus::Connecting { queued: 0 });
     emit_status(&app, &state);
-    tray::update_icon(&app, &ConnectionStatus::Connecting);
+    tray::update_icon(&app, &ConnectionStatus::Connecting { queued: 0 });
 
     let ws_url = build_ws_url(&server_url, &room)?;
 
@@ -49,8 +73,14 @@ pub async fn connect(
 
 pub fn disconnect(app: &AppHandle, state: &AppState) {
     state.set_sender(None);
+    state.clear_outbox();
+    state.set_room_state(None);
+    state.clear_members();
+    tray::update_members(app, &[]);
+    state.set_negotiated(None);
     state.set_room(None);
     state.set_server(None);
+    state.set_credentials(RoomCredentials::default(), "");
     state.set_status(ConnectionStatus::Disconnected);
     emit_status(app, state);
     tray::update_icon(app, &ConnectionStatus::Disconnected);
@@ -86,11 +116,15 @@ async fn run_connection_loop(
 
         info!("Connecting to {}", url);
 
-        match connect_async(url.as_str()).await {
-            Ok((ws_stream, _)) => {
-                info!("Connected to WebSocket");
+        match connect_and_handshake(&state, &url).await {
+            Ok((mut write, mut read, negotiated)) => {
+                info!(
+                    "Connected to WebSocket (protocol v{}, capabilities {:?})",
+                    negotiated.version, negotiated.capabilities
+                );
                 backoff_ms = INITIAL_BACKOFF_MS;
                 attempt = 0;
+                state.set_negotiated(Some(negotiated));
 
                 let room = state.get_room().unwrap_or_default();
                 state.set_status(ConnectionStatus::Connected {
@@ -106,7 +140,13 @@ async fn run_connection_loop(
                     },
                 );
 
-                let (mut write, mut read) = ws_stream.split();
+                // Resend control messages the server never acknowledged
+                if state.supports(Capability::Acks) {
+                    state.resend_unacked();
+                } else {
+                    state.clear_unacked();
+                }
+                state.flush_outbox();
 
                 // Ping interval
                 let ping_state = state.clone();
@@ -131,11 +171,14 @@ async fn run_connection_loop(
                     }
                 });
 
+                let drift_handle = drift::spawn(app.clone(), state.clone());
+
                 loop {
                     tokio::select! {
                         // Outgoing messages
-                        Some(msg) = rx.recv() => {
-                            let json = match serde_json::to_string(&msg) {
+                        Some(mut msg) = rx.recv() => {
+                            state.stamp(&mut msg);
+                            let json = match encode(&state, &msg) {
                                 Ok(j) => j,
                                 Err(e) => {
                                     error!("Failed to serialize message: {}", e);
@@ -144,8 +187,13 @@ async fn run_connection_loop(
                             };
                             if let Err(e) = write.send(WsMessage::Text(json.into())).await {
                                 error!("Failed to send message: {}", e);
+                                // Tracked messages go out again with the unacked ones
+                                if msg.seq.is_none() || !state.supports(Capability::Acks) {
+                                    state.queue_message(msg);
+                                }
                                 break;
                             }
+                            hooks::run(&app, &state, &msg, Direction::Out);
                         }
                         // Incoming messages
                         Some(result) = read.next() => {
@@ -172,13 +220,27 @@ async fn run_connection_loop(
                     // Check if intentionally disconnected
                     if state.ws_sender.read().is_none() {
                         ping_handle.abort();
+                        drift_handle.abort();
                         return;
                     }
                 }
 
                 ping_handle.abort();
+                drift_handle.abort();
+                state.set_negotiated(None);
             }
-            Err(e) => {
+            Err(ConnectError::Incompatible(reason)) => {
+                error!("Server is incompatible: {}", reason);
+                // Retrying won't help, so stop the loop like an intentional disconnect
+                give_up(&app, &state, ConnectionStatus::Incompatible { reason });
+                return;
+            }
+            Err(ConnectError::Unauthorized(reason)) => {
+                error!("Server rejected room credentials: {}", reason);
+                give_up(&app, &state, ConnectionStatus::AuthFailed { reason });
+                return;
+            }
+            Err(ConnectError::Failed(e)) => {
                 error!("Failed to connect: {}", e);
             }
         }
@@ -190,24 +252,160 @@ async fn run_connection_loop(
 
         // Reconnect with backoff
         attempt += 1;
-        state.set_status(ConnectionStatus::Reconnecting { attempt });
+        let status = ConnectionStatus::Reconnecting {
+            attempt,
+            queued: state.queued_count(),
+        };
+        state.set_status(status.clone());
         emit_status(&app, &state);
-        tray::update_icon(&app, &ConnectionStatus::Reconnecting { attempt });
+        tray::update_icon(&app, &status);
 
         warn!("Reconnecting in {}ms (attempt {})", backoff_ms, attempt);
         tokio::time::sleep(Duration::from_millis(backoff_ms)).await;
 
         backoff_ms = (backoff_ms * 2).min(MAX_BACKOFF_MS);
 
-        // Recreate receiver channel
+        // Recreate receiver channel, keeping anything the old one still held
+        while let Ok(msg) = rx.try_recv() {
+            state.queue_message(msg);
+        }
         let (tx, new_rx) = unbounded_channel::<Message>();
         rx = new_rx;
         state.set_sender(Some(tx));
     }
 }
 
-fn handle_message(app: &AppHandle, state: &AppState, text: &str) {
-    let msg: Message = match serde_json::from_str(text) {
+/// Stops reconnecting and leaves `status` showing until the user acts.
+fn give_up(app: &AppHandle, state: &AppState, status: ConnectionStatus) {
+    state.set_sender(None);
+    state.set_status(status.clone());
+    emit_status(app, state);
+    tray::update_icon(app, &status);
+}
+
+enum ConnectError {
+    /// Protocol versions can't interoperate; reconnecting won't help.
+    Incompatible(String),
+    /// The server refused our token or passphrase; reconnecting won't help.
+    Unauthorized(String),
+    /// Transport or handshake failure; worth retrying.
+    Failed(String),
+}
+
+async fn connect_and_handshake(
+    state: &AppState,
+    url: &Url,
+) -> Result<(WsWrite, WsRead, Negotiated), ConnectError> {
+    let mut request = url
+        .as_str()
+        .into_client_request()
+        .map_err(|e| ConnectError::Failed(e.to_string()))?;
+    if let Some(token) = state.token() {
+        let value = HeaderValue::from_str(&format!("Bearer {}", token))
+            .map_err(|_| ConnectError::Unauthorized("token contains invalid characters".into()))?;
+        request.headers_mut().insert(header::AUTHORIZATION, value);
+    }
+
+    let (ws_stream, _) = connect_async(request).await.map_err(|e| match e {
+        tungstenite::Error::Http(response)
+            if matches!(
+                response.status(),
+                StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN
+            ) =>
+        {
+            ConnectError::Unauthorized(format!("server responded {}", response.status()))
+        }
+        e => ConnectError::Failed(e.to_string()),
+    })?;
+    let (mut write, mut read) = ws_stream.split();
+
+    let mut hello = Message::hello(state.display_name());
+    state.stamp(&mut hello);
+    let hello = encode(state, &hello).map_err(ConnectError::Failed)?;
+    write
+        .send(WsMessage::Text(hello.into()))
+        .await
+        .map_err(|e| ConnectError::Failed(e.to_string()))?;
+
+    // Only a welcome proves anything about compatibility. Silence may just
+    // be a slow link, so it's retried like any other failed attempt.
+    let welcome = tokio::time::timeout(Duration::from_millis(HANDSHAKE_TIMEOUT_MS), async {
+        while let Some(result) = read.next().await {
+            match result {
+                Ok(WsMessage::Text(text)) => match serde_json::from_str::<Message>(&text) {
+                    Ok(msg) => match msg.kind {
+                        MessageKind::Welcome {
+                            version,
+                            capabilities,
+                        } => return Ok((version, capabilities)),
+                        kind => warn!("Expected welcome, ignoring {}", kind.type_name()),
+                    },
+                    Err(e) => warn!("Failed to parse message: {}", e),
+                },
+                Ok(WsMessage::Close(_)) => {
+                    return Err(ConnectError::Failed(
+                        "closed by server during handshake".into(),
+                    ));
+                }
+                Err(e) => return Err(ConnectError::Failed(e.to_string())),
+                _ => {}
+            }
+        }
+        Err(ConnectError::Failed("stream ended during handshake".into()))
+    })
+    .await
+    .map_err(|_| {
+        ConnectError::Failed(format!(
+            "no welcome from server within {}ms",
+            HANDSHAKE_TIMEOUT_MS
+        ))
+    })??;
+
+    let negotiated =
+        Negotiated::from_welcome(welcome.0, &welcome.1).map_err(ConnectError::Incompatible)?;
+
+    Ok((write, read, negotiated))
+}
+
+/// Serialises an outgoing message, encrypting playback messages in
+/// end-to-end mode and signing it if the room has a passphrase.
+fn encode(state: &AppState, msg: &Message) -> Result<String, String> {
+    let mut value = serde_json::to_value(msg).map_err(|e| e.to_string())?;
+    if msg.kind.is_private()
+        && let Some(key) = state.room_key()
+    {
+        key.seal(&mut value)?;
+    }
+    if let Some(key) = state.signing_key() {
+        auth::sign(&mut value, &key);
+    }
+    Ok(value.to_string())
+}
+
+fn handle_message<R: Runtime>(app: &AppHandle<R>, state: &Arc<AppState>, text: &str) {
+    let mut raw: Value = match serde_json::from_str(text) {
+        Ok(v) => v,
+        Err(e) => {
+            warn!("Failed to parse message: {}", e);
+            return;
+        }
+    };
+    let verified = match state.signing_key() {
+        Some(key) => auth::verify(&mut raw, &key),
+        None => Ok(()),
+    };
+    if crypto::is_sealed(&raw) {
+        let opened = match state.room_key() {
+            Some(key) => key.open(&mut raw),
+            None => Err(DecryptError::NoKey),
+        };
+        if let Err(e) = opened {
+            warn!("Cannot decrypt message: {}", e);
+            emit_decrypt_failure(app, &raw, e);
+            return;
+        }
+    }
+    let mut msg: Message = match serde_json::from_value(raw) {
         Ok(m) => m,
         Err(e) => {
             warn!("Failed to parse message: {}", e);
@@ -215,29 +413,553 @@ fn handle_message(app: &AppHandle, state: &AppState, text: &str) {
         }
     };
 
-    if msg.is_pong() {
-        if let Some(ping_time) = state.take_last_ping() {
-            let latency = (protocol::now_ms() - ping_time) as u32;
-            if let ConnectionStatus::Connected { room, .. } = state.get_status() {
-                state.set_status(ConnectionStatus::Connected {
-                    room,
-                    latency_ms: Some(latency),
+    // Only room members know the passphrase, so only they may drive playback
+    if msg.kind.is_private()
+        && let Err(e) = &verified
+    {
+        warn!("Rejecting {} message: {}", msg.kind.type_name(), e);
+        echo::report(app, &msg, SuppressReason::Unauthenticated, e.to_string());
+        return;
+    }
+
+    // Other clients stamp in server time; show it on our clock
+    msg.timestamp = state.to_local_time(msg.timestamp);
+
+    // Drop control messages re-delivered after a retransmit
+    if msg.kind.is_control()
+        && let (Some(client_id), Some(seq)) = (&msg.client_id, msg.seq)
+        && !state.mark_seen(client_id, seq)
+    {
+        debug!("Dropping duplicate {} seq {}", client_id, seq);
+        return;
+    }
+
+    // Our own messages ran their hooks when we sent them
+    if !state.is_own(&msg) {
+        hooks::run(app, state, &msg, Direction::In);
+    }
+
+    match &msg.kind {
+        MessageKind::Pong { server_time } => {
+            if let Some(ping_time) = state.take_last_ping() {
+                let now = protocol::now_ms();
+                let latency = (now - ping_time) as u32;
+                if let Some(server_time) = *server_time {
+                    state.record_clock_sample(ping_time, server_time, now);
+                    debug!("Clock offset estimate: {:?}ms", state.clock_offset());
+                }
+                if let ConnectionStatus::Connected { room, .. } = state.get_status() {
+                    state.set_status(ConnectionStatus::Connected {
+                        room,
+                        latency_ms: Some(latency),
+                    });
+                    emit_status(app, state);
+                }
+            }
+            return;
+        }
+        MessageKind::Position { position, playing } => {
+            if let Some(client_id) = &msg.client_id
+                && !state.is_own(&msg)
+            {
+                state.record_peer_position(client_id, *position, *playing, msg.timestamp);
+            }
+            return;
+        }
+        MessageKind::Ack { seq } => {
+            if !state.ack(*seq) {
+                debug!("Ack for unknown seq {}", seq);
+            }
+            return;
+        }
+        MessageKind::RoomState(snapshot) => {
+            info!("Joined room: {:?}", snapshot);
+            state.set_room_state(Some(snapshot.clone()));
+            let _ = app.emit("room-state", snapshot);
+            if state.align_on_join() {
+                // Snapshots aren't private, so one that isn't signed by a room
+                // member may come from anyone; don't let it steer the player
+                if let Err(e) = &verified {
+                    warn!("Not aligning with unverified room state: {}", e);
+                    return;
+                }
+                // Backends talk to players synchronously; keep the socket loop free
+                let (app, state, snapshot) = (app.clone(), state.clone(), snapshot.clone());
+                tauri::async_runtime::spawn_blocking(move || {
+                    align_with_room(&app, &state, &snapshot, msg.timestamp);
                 });
-                emit_status(app, state);
             }
+            return;
+        }
+        MessageKind::Members { members } => {
+            state.set_members(members.clone());
+            let members = state.get_members();
+            let _ = app.emit("members", &members);
+            tray::update_members(app, &members);
+            return;
+        }
+        MessageKind::MemberJoined { member } => {
+            let member = state.add_member(member.clone());
+            info!("Member joined: {}", member.client_id);
+            let _ = app.emit("member-joined", &member);
+            tray::update_members(app, &state.get_members());
+            return;
+        }
+        MessageKind::MemberLeft { member_id } => {
+            if let Some(member) = state.remove_member(member_id) {
+                info!("Member left: {}", member.client_id);
+                let _ = app.emit("member-left", &member);
+                tray::update_members(app, &state.get_members());
+            }
+            return;
         }
+        MessageKind::Unknown(raw) => {
+            warn!("Ignoring unrecognised message: {}", raw);
+            return;
+        }
+        _ => {}
+    }
+
+    if let Some(client_id) = &msg.client_id {
+        state.touch_member(client_id);
+    }
+
+    if let Some(snapshot) = state.update_room_state(&msg) {
+        let _ = app.emit("room-state", snapshot);
+    }
+
+    // Our own command relayed back; we already acted on it locally
+    if state.is_own(&msg) {
+        echo::report(
+            app,
+            &msg,
+            SuppressReason::OwnEcho,
+            "sent by this client".into(),
+        );
         return;
     }
 
     // Emit event to frontend
-    let _ = app.emit("sync-event", &msg);
+    emit_sync_event(app, state, &msg);
 
     // Simulate media key if it's a playback control message
-    if msg.is_toggle() || msg.is_play() || msg.is_pause() {
-        crate::media::simulate_toggle(state);
+    match msg.kind {
+        MessageKind::Toggle => {
+            let (app, media_state) = (app.clone(), state.clone());
+            schedule::run_at(state, msg.execute_at, move || {
+                echo::apply_toggle(&app, &media_state, &msg);
+            });
+        }
+        MessageKind::Play
+        | MessageKind::Pause
+        | MessageKind::Next
+        | MessageKind::Previous
+        | MessageKind::Stop
+        | MessageKind::VolumeUp
+        | MessageKind::VolumeDown
+        | MessageKind::Mute => {
+            let (app, media_state) = (app.clone(), state.clone());
+            schedule::run_at(state, msg.execute_at, move || {
+                echo::apply(&app, &media_state, &msg);
+            });
+        }
+        MessageKind::Seek { position } => {
+            let (app, media_state) = (app.clone(), state.clone());
+            schedule::run_at(state, msg.execute_at, move || {
+                media::seek(&app, &media_state, position)
+            });
+        }
+        _ => {}
+    }
+}
+
+/// Brings the local player in line with the room snapshot received on join.
+fn align_with_room<R: Runtime>(
+    app: &AppHandle<R>,
+    state: &AppState,
+    snapshot: &RoomSnapshot,
+    snapshot_time: i64,
+) {
+    if let Some(position) = snapshot.position {
+        // A playing room has moved on since the snapshot was taken
+        let elapsed = if snapshot.playing {
+            (protocol::now_ms() - snapshot_time).max(0)
+        } else {
+            0
+        };
+        if state.supports(Capability::Seek) {
+            media::seek(app, state, position + elapsed);
+        }
     }
+
+    // Key simulation can only flip playback, not report it, so only match
+    // the room's play/pause state when the backend can tell us ours
+    match media::is_playing(state) {
+        Some(playing) if playing != snapshot.playing => {
+            if snapshot.playing {
+                media::play(app, state);
+            } else {
+                media::pause(app, state);
+            }
+        }
+        Some(_) => {}
+        None => debug!(
+            "Room is {}; backend can't report local play state, leaving it as is",
+            if snapshot.playing {
+                "playing"
+            } else {
+                "paused"
+            }
+        ),
+    }
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct DecryptFailure {
+    client_id: Option<String>,
+    seq: Option<u64>,
+    reason: String,
+}
+
+/// Emits `cannot-decrypt` for an encrypted message we couldn't open.
+fn emit_decrypt_failure<R: Runtime>(app: &AppHandle<R>, raw: &Value, error: DecryptError) {
+    let failure = DecryptFailure {
+        client_id: raw
+            .get("clientId")
+            .and_then(Value::as_str)
+            .map(String::from),
+        seq: raw.get("seq").and_then(Value::as_u64),
+        reason: error.to_string(),
+    };
+    let _ = app.emit("cannot-decrypt", failure);
 }
 
-fn emit_status(app: &AppHandle, state: &AppState) {
+#[derive(Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Origin {
+    #[serde(rename = "self")]
+    Own,
+    Remote,
+}
+
+#[derive(Clone, Serialize)]
+struct SyncEvent<'a> {
+    #[serde(flatten)]
+    msg: &'a Message,
+    origin: Origin,
+}
+
+/// Emits `sync-event` for the event log, tagged with whether it's ours.
+pub fn emit_sync_event<R: Runtime>(app: &AppHandle<R>, state: &AppState, msg: &Message) {
+    let origin = if state.is_own(msg) {
+        Origin::Own
+    } else {
+        Origin::Remote
+    };
+    let _ = app.emit("sync-event", SyncEvent { msg, origin });
+}
+
+/// Sends `msg`, or queues it while offline and tells the UI the queue grew.
+pub fn send<R: Runtime>(app: &AppHandle<R>, state: &AppState, msg: Message) -> Result<(), String> {
+    state.send_message(msg)?;
+    if !matches!(state.get_status(), ConnectionStatus::Connected { .. }) {
+        emit_status(app, state);
+    }
+    Ok(())
+}
+
+fn emit_status<R: Runtime>(app: &AppHandle<R>, state: &AppState) {
     let _ = app.emit("connection-status", state.get_status());
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use serde_json::json;
+    use tauri::test::{mock_app, MockRuntime};
+    use tauri::App;
+
+    use super::*;
+    use crate::media::{BackendKind, MediaCall, MediaSettings, MediaTarget};
+
+    fn setup() -> (App<MockRuntime>, Arc<AppState>) {
+        let state = Arc::new(AppState::new());
+        state
+            .set_media_targets(vec![MediaTarget::new(BackendKind::Recording)])
+            .unwrap();
+        (mock_app(), state)
+    }
+
+    fn receive(app: &App<MockRuntime>, state: &Arc<AppState>, msg: Value) {
+        handle_message(app.handle(), state, &msg.to_string());
+    }
+
+    /// Media calls run on a blocking thread; waits up to a second for
+    /// `count` of them, then returns whatever was recorded.
+    fn calls(state: &AppState, count: usize) -> Vec<MediaCall> {
+        let deadline = Instant::now() + Duration::from_secs(1);
+        while state.recorder.calls().len() < count && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        state.recorder.calls()
+    }
+
+    #[test]
+    fn remote_play_starts_the_player() {
+        let (app, state) = setup();
+        receive(
+            &app,
+            &state,
+            json!({"type": "play", "clientId": "peer", "seq": 1}),
+        );
+        assert_eq!(calls(&state, 1), vec![MediaCall::Play]);
+    }
+
+    #[test]
+    fn remote_seek_seeks_the_player() {
+        let (app, state) = setup();
+        receive(
+            &app,
+            &state,
+            json!({"type": "seek", "position": 42_000, "clientId": "peer", "seq": 1}),
+        );
+        assert_eq!(
+            calls(&state, 1),
+            vec![MediaCall::Seek {
+                position_ms: 42_000
+            }]
+        );
+    }
+
+    #[test]
+    fn far_off_deadline_applies_right_away() {
+        let (app, state) = setup();
+        let hour_ahead = protocol::now_ms() + 3_600_000;
+        receive(
+            &app,
+            &state,
+            json!({"type": "play", "executeAt": hour_ahead, "clientId": "peer", "seq": 1}),
+        );
+        assert_eq!(calls(&state, 1), vec![MediaCall::Play]);
+    }
+
+    #[test]
+    fn play_and_pause_skip_the_cooldown() {
+        let (app, state) = setup();
+        receive(
+            &app,
+            &state,
+            json!({"type": "play", "clientId": "a", "seq": 1}),
+        );
+        assert_eq!(calls(&state, 1), vec![MediaCall::Play]);
+        receive(
+            &app,
+            &state,
+            json!({"type": "pause", "clientId": "b", "seq": 1}),
+        );
+        assert_eq!(calls(&state, 2), vec![MediaCall::Play, MediaCall::Pause]);
+    }
+
+    #[test]
+    fn blind_play_and_pause_keep_the_cooldown() {
+        let (app, state) = setup();
+        // An mpv that isn't running can't say whether it's playing
+        state
+            .set_media_settings(MediaSettings {
+                mpv_socket: "/nonexistent/mpvsocket".into(),
+                ..Default::default()
+            })
+            .unwrap();
+        state
+            .set_media_targets(vec![
+                MediaTarget::new(BackendKind::Recording),
+                MediaTarget::new(BackendKind::Mpv),
+            ])
+            .unwrap();
+
+        receive(
+            &app,
+            &state,
+            json!({"type": "play", "clientId": "a", "seq": 1}),
+        );
+        assert_eq!(calls(&state, 1), vec![MediaCall::Play]);
+        receive(
+            &app,
+            &state,
+            json!({"type": "pause", "clientId": "b", "seq": 1}),
+        );
+        std::thread::sleep(Duration::from_millis(100));
+        assert_eq!(state.recorder.calls(), vec![MediaCall::Play]);
+    }
+
+    #[test]
+    fn commands_no_backend_reacts_to_are_skipped() {
+        let (app, state) = setup();
+        let mut target = MediaTarget::new(BackendKind::Recording);
+        target.types = vec!["seek".into()];
+        state.set_media_targets(vec![target]).unwrap();
+
+        receive(
+            &app,
+            &state,
+            json!({"type": "play", "clientId": "peer", "seq": 1}),
+        );
+        receive(
+            &app,
+            &state,
+            json!({"type": "seek", "position": 9, "clientId": "peer", "seq": 2}),
+        );
+        assert_eq!(calls(&state, 2), vec![MediaCall::Seek { position_ms: 9 }]);
+    }
+
+    #[test]
+    fn retransmitted_command_applies_once() {
+        let (app, state) = setup();
+        let seek = json!({"type": "seek", "position": 1_000, "clientId": "peer", "seq": 7});
+        receive(&app, &state, seek.clone());
+        receive(&app, &state, seek);
+        assert_eq!(calls(&state, 2).len(), 1);
+    }
+
+    #[test]
+    fn toggle_bursts_resolve_to_net_presses() {
+        let (app, state) = setup();
+        for seq in 1..=3 {
+            receive(
+                &app,
+                &state,
+                json!({"type": "toggle", "clientId": "peer", "seq": seq}),
+            );
+        }
+        // The first toggle acts, the two that follow cancel out once the
+        // coalescing window has passed
+        std::thread::sleep(Duration::from_millis(700));
+        assert_eq!(calls(&state, 1), vec![MediaCall::Toggle]);
+
+        for seq in 4..=5 {
+            receive(
+                &app,
+                &state,
+                json!({"type": "toggle", "clientId": "peer", "seq": seq}),
+            );
+        }
+        // The second of these is merged in once the window closes
+        assert_eq!(
+            calls(&state, 3),
+            vec![MediaCall::Toggle, MediaCall::Toggle, MediaCall::Toggle]
+        );
+    }
+
+    #[test]
+    fn unsigned_command_is_rejected_in_protected_room() {
+        let (app, state) = setup();
+        state.set_credentials(
+            RoomCredentials {
+                passphrase: Some("secret".into()),
+                ..Default::default()
+            },
+            "room",
+        );
+
+        receive(
+            &app,
+            &state,
+            json!({"type": "play", "clientId": "peer", "seq": 1}),
+        );
+        let mut signed = json!({"type": "seek", "position": 5, "clientId": "peer", "seq": 2});
+        auth::sign(&mut signed, &state.signing_key().unwrap());
+        receive(&app, &state, signed);
+
+        assert_eq!(calls(&state, 1), vec![MediaCall::Seek { position_ms: 5 }]);
+        std::thread::sleep(Duration::from_millis(100));
+        assert_eq!(state.recorder.calls().len(), 1);
+    }
+
+    #[test]
+    fn only_verified_room_state_aligns_a_protected_room() {
+        let (app, state) = setup();
+        state.set_align_on_join(true);
+        state.set_credentials(
+            RoomCredentials {
+                passphrase: Some("secret".into()),
+                ..Default::default()
+            },
+            "room",
+        );
+
+        let snapshot = json!({"type": "roomState", "playing": true, "memberCount": 2});
+        receive(&app, &state, snapshot.clone());
+        std::thread::sleep(Duration::from_millis(100));
+        assert!(state.recorder.calls().is_empty());
+
+        let mut signed = snapshot;
+        auth::sign(&mut signed, &state.signing_key().unwrap());
+        receive(&app, &state, signed);
+        assert_eq!(calls(&state, 1), vec![MediaCall::Play]);
+    }
+
+    /// Accepts one WebSocket connection, takes the client's hello and
+    /// answers with `frames`, closing afterwards if `close` is set.
+    async fn fake_server(frames: Vec<Value>, close: bool) -> Url {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!("ws://{}/ws", listener.local_addr().unwrap())).unwrap();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+            ws.next().await;
+            for frame in frames {
+                ws.send(WsMessage::Text(frame.to_string().into()))
+                    .await
+                    .unwrap();
+            }
+            if close {
+                let _ = ws.close(None).await;
+            } else {
+                std::future::pending::<()>().await;
+            }
+        });
+        url
+    }
+
+    #[tokio::test]
+    async fn handshake_waits_past_other_messages_for_welcome() {
+        let url = fake_server(
+            vec![
+                json!({"type": "roomState", "playing": false}),
+                json!({"type": "welcome", "version": 1, "capabilities": ["seek", "teleport"]}),
+            ],
+            false,
+        )
+        .await;
+
+        let Ok((_, _, negotiated)) = connect_and_handshake(&AppState::new(), &url).await else {
+            panic!("handshake failed");
+        };
+        assert!(negotiated.supports(Capability::Seek));
+        assert!(!negotiated.supports(Capability::Acks));
+    }
+
+    #[tokio::test]
+    async fn handshake_without_welcome_is_retried() {
+        let url = fake_server(vec![json!({"type": "roomState", "playing": false})], true).await;
+        assert!(matches!(
+            connect_and_handshake(&AppState::new(), &url).await,
+            Err(ConnectError::Failed(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn mismatched_welcome_is_incompatible() {
+        let url = fake_server(
+            vec![json!({"type": "welcome", "version": 99, "capabilities": []})],
+            false,
+        )
+        .await;
+        assert!(matches!(
+            connect_and_handshake(&AppState::new(), &url).await,
+            Err(ConnectError::Incompatible(_))
+        ));
+    }
+}
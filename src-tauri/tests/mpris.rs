@@ -0,0 +1,352 @@
+//! Drives `MprisBackend` against a fake player on a private session bus.
+//! Skipped when `dbus-daemon` isn't installed.
+#![cfg(target_os = "linux")]
+
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader};
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use dragonfox_mediasync_client_lib::{
+    MediaAction, MediaBackend, MprisBackend, PlaybackState, PlayerChange,
+};
+use zbus::blocking::Connection;
+use zbus::interface;
+use zbus::zvariant::{ObjectPath, OwnedValue, Value};
+
+const TRACK_ID: &str = "/org/mpris/MediaPlayer2/Track/1";
+
+/// The session bus address is process-wide, so tests take turns.
+static BUS_LOCK: Mutex<()> = Mutex::new(());
+
+/// A private `dbus-daemon`, installed as the session bus while it lives.
+struct Bus {
+    daemon: Child,
+    _lock: MutexGuard<'static, ()>,
+}
+
+impl Bus {
+    fn start() -> Option<Self> {
+        let lock = BUS_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let mut daemon = match Command::new("dbus-daemon")
+            .args(["--session", "--nofork", "--print-address"])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+        {
+            Ok(daemon) => daemon,
+            Err(e) => {
+                eprintln!("skipping: cannot start dbus-daemon: {}", e);
+                return None;
+            }
+        };
+
+        let mut address = String::new();
+        BufReader::new(daemon.stdout.take().unwrap())
+            .read_line(&mut address)
+            .unwrap();
+        // SAFETY: BUS_LOCK keeps other tests from touching the environment
+        unsafe { std::env::set_var("DBUS_SESSION_BUS_ADDRESS", address.trim()) };
+
+        Some(Self {
+            daemon,
+            _lock: lock,
+        })
+    }
+}
+
+impl Drop for Bus {
+    fn drop(&mut self) {
+        let _ = self.daemon.kill();
+        let _ = self.daemon.wait();
+    }
+}
+
+/// Just enough of `org.mpris.MediaPlayer2.Player` for the backend, logging
+/// every method call.
+struct FakePlayer {
+    playing: bool,
+    position_us: i64,
+    calls: Arc<Mutex<Vec<String>>>,
+}
+
+impl FakePlayer {
+    fn record(&self, call: String) {
+        self.calls.lock().unwrap().push(call);
+    }
+}
+
+#[interface(name = "org.mpris.MediaPlayer2.Player")]
+impl FakePlayer {
+    fn play(&mut self) {
+        self.record("Play".into());
+        self.playing = true;
+    }
+
+    fn pause(&mut self) {
+        self.record("Pause".into());
+        self.playing = false;
+    }
+
+    fn play_pause(&mut self) {
+        self.record("PlayPause".into());
+        self.playing = !self.playing;
+    }
+
+    fn next(&mut self) {
+        self.record("Next".into());
+    }
+
+    fn set_position(&mut self, track_id: ObjectPath<'_>, position: i64) {
+        self.record(format!("SetPosition {} {}", track_id, position));
+        self.position_us = position;
+    }
+
+    #[zbus(property)]
+    fn playback_status(&self) -> String {
+        if self.playing { "Playing" } else { "Paused" }.into()
+    }
+
+    #[zbus(property)]
+    fn position(&self) -> i64 {
+        self.position_us
+    }
+
+    #[zbus(property)]
+    fn metadata(&self) -> HashMap<String, OwnedValue> {
+        let track_id = Value::from(ObjectPath::try_from(TRACK_ID).unwrap());
+        HashMap::from([("mpris:trackid".into(), track_id.try_into().unwrap())])
+    }
+}
+
+/// Stands in for the media key fallback, logging what it's asked to press.
+#[derive(Default)]
+struct FakeKeys {
+    pressed: Mutex<Vec<MediaAction>>,
+}
+
+impl MediaBackend for FakeKeys {
+    fn name(&self) -> &'static str {
+        "fake keys"
+    }
+
+    fn play(&self) -> Result<(), String> {
+        self.perform(MediaAction::PlayPause)
+    }
+
+    fn pause(&self) -> Result<(), String> {
+        self.perform(MediaAction::PlayPause)
+    }
+
+    fn toggle(&self) -> Result<(), String> {
+        self.perform(MediaAction::PlayPause)
+    }
+
+    fn seek(&self, _position_ms: i64) -> Result<(), String> {
+        Err("keys cannot seek".into())
+    }
+
+    fn query(&self) -> Result<PlaybackState, String> {
+        Err("keys cannot report playback".into())
+    }
+
+    fn perform(&self, action: MediaAction) -> Result<(), String> {
+        self.pressed.lock().unwrap().push(action);
+        Ok(())
+    }
+}
+
+/// Puts a player named `org.mpris.MediaPlayer2.<name>` on the bus until
+/// the returned connection is dropped.
+fn serve(name: &str, playing: bool) -> (Connection, Arc<Mutex<Vec<String>>>) {
+    let calls = Arc::new(Mutex::new(Vec::new()));
+    let player = FakePlayer {
+        playing,
+        position_us: 0,
+        calls: calls.clone(),
+    };
+    let conn = zbus::blocking::connection::Builder::session()
+        .unwrap()
+        .name(format!("org.mpris.MediaPlayer2.{}", name))
+        .unwrap()
+        .serve_at("/org/mpris/MediaPlayer2", player)
+        .unwrap()
+        .build()
+        .unwrap();
+    (conn, calls)
+}
+
+fn taken(calls: &Mutex<Vec<String>>) -> Vec<String> {
+    std::mem::take(&mut *calls.lock().unwrap())
+}
+
+#[test]
+fn controls_the_running_player() {
+    let Some(_bus) = Bus::start() else {
+        return;
+    };
+    let (_conn, calls) = serve("fake", false);
+    let backend = MprisBackend::new(None);
+
+    assert_eq!(
+        backend.query().unwrap(),
+        PlaybackState {
+            playing: false,
+            position_ms: Some(0),
+        }
+    );
+
+    backend.play().unwrap();
+    assert!(backend.query().unwrap().playing);
+    backend.pause().unwrap();
+    backend.toggle().unwrap();
+    backend.perform(MediaAction::Next).unwrap();
+    assert_eq!(taken(&calls), ["Play", "Pause", "PlayPause", "Next"]);
+
+    backend.seek(1_500).unwrap();
+    assert_eq!(taken(&calls), [format!("SetPosition {} 1500000", TRACK_ID)]);
+    assert_eq!(backend.query().unwrap().position_ms, Some(1_500));
+}
+
+#[test]
+fn prefers_the_configured_player() {
+    let Some(_bus) = Bus::start() else {
+        return;
+    };
+    let (_playing, playing_calls) = serve("playing", true);
+    let (_idle, idle_calls) = serve("idle", false);
+
+    // Without a preference, the one that's playing wins
+    MprisBackend::new(None).toggle().unwrap();
+    assert_eq!(taken(&playing_calls), ["PlayPause"]);
+
+    MprisBackend::new(Some("idle".into())).toggle().unwrap();
+    assert_eq!(taken(&idle_calls), ["PlayPause"]);
+    assert!(taken(&playing_calls).is_empty());
+}
+
+#[test]
+fn presses_keys_without_a_player() {
+    let Some(_bus) = Bus::start() else {
+        return;
+    };
+    let keys = Arc::new(FakeKeys::default());
+    let backend = MprisBackend::new(None).with_fallback(Some(keys.clone()));
+
+    backend.play().unwrap();
+    backend.perform(MediaAction::Next).unwrap();
+    assert_eq!(
+        *keys.pressed.lock().unwrap(),
+        [MediaAction::PlayPause, MediaAction::Next]
+    );
+    assert!(backend.seek(1_000).is_err());
+    assert!(backend.query().is_err());
+
+    // A running player takes over from the keys
+    let (_conn, calls) = serve("fake", false);
+    backend.play().unwrap();
+    assert_eq!(taken(&calls), ["Play"]);
+    assert_eq!(keys.pressed.lock().unwrap().len(), 2);
+}
+
+#[test]
+fn fails_without_a_player_or_fallback() {
+    let Some(_bus) = Bus::start() else {
+        return;
+    };
+    let backend = MprisBackend::new(None).with_fallback(None);
+    assert_eq!(backend.play(), Err("No MPRIS player is running".into()));
+    assert!(backend.query().is_err());
+}
+
+#[test]
+fn watch_reports_changes_and_stops_promptly() {
+    let Some(_bus) = Bus::start() else {
+        return;
+    };
+    let (conn, _) = serve("fake", false);
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let (changes_tx, changes) = mpsc::channel();
+    let (done_tx, done) = mpsc::channel();
+    let watch_stop = stop.clone();
+    std::thread::spawn(move || {
+        let report = |change| changes_tx.send(change).unwrap();
+        let result = MprisBackend::new(None).watch(&report, &watch_stop);
+        done_tx.send(result).unwrap();
+    });
+    // Give the watch time to subscribe
+    std::thread::sleep(Duration::from_millis(300));
+
+    let status = HashMap::from([("PlaybackStatus", Value::from("Playing"))]);
+    conn.emit_signal(
+        None::<()>,
+        "/org/mpris/MediaPlayer2",
+        "org.freedesktop.DBus.Properties",
+        "PropertiesChanged",
+        &(
+            "org.mpris.MediaPlayer2.Player",
+            status,
+            Vec::<String>::new(),
+        ),
+    )
+    .unwrap();
+    conn.emit_signal(
+        None::<()>,
+        "/org/mpris/MediaPlayer2",
+        "org.mpris.MediaPlayer2.Player",
+        "Seeked",
+        &(5_000_000i64,),
+    )
+    .unwrap();
+
+    let timeout = Duration::from_secs(2);
+    assert_eq!(changes.recv_timeout(timeout), Ok(PlayerChange::Played));
+    assert_eq!(
+        changes.recv_timeout(timeout),
+        Ok(PlayerChange::Seeked { position_ms: 5_000 })
+    );
+
+    // No further signals arrive, yet the watch must still wind down
+    stop.store(true, Ordering::Relaxed);
+    assert!(done.recv_timeout(timeout).is_ok());
+}
+
+#[test]
+fn watch_ignores_players_it_does_not_control() {
+    let Some(_bus) = Bus::start() else {
+        return;
+    };
+    // The playing one is in control
+    let (controlled, _) = serve("music", true);
+    let (other, _) = serve("browser", false);
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let (changes_tx, changes) = mpsc::channel();
+    let watch_stop = stop.clone();
+    std::thread::spawn(move || {
+        let report = |change| changes_tx.send(change).unwrap();
+        let _ = MprisBackend::new(None).watch(&report, &watch_stop);
+    });
+    std::thread::sleep(Duration::from_millis(300));
+
+    for (conn, position_us) in [(&other, 1_000_000i64), (&controlled, 2_000_000)] {
+        conn.emit_signal(
+            None::<()>,
+            "/org/mpris/MediaPlayer2",
+            "org.mpris.MediaPlayer2.Player",
+            "Seeked",
+            &(position_us,),
+        )
+        .unwrap();
+    }
+
+    let timeout = Duration::from_secs(2);
+    assert_eq!(
+        changes.recv_timeout(timeout),
+        Ok(PlayerChange::Seeked { position_ms: 2_000 })
+    );
+    stop.store(true, Ordering::Relaxed);
+}
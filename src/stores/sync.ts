@@ -8,14 +8,88 @@ export interface SyncEvent {
   timestamp: number
   clientId?: string
   position?: number
+  suppressed?: string
+  detail?: string
   direction: 'in' | 'out'
 }
 
 export interface ConnectionStatus {
-  status: 'disconnected' | 'connecting' | 'connected' | 'reconnecting'
+  status: 'disconnected' | 'connecting' | 'connected' | 'reconnecting' | 'incompatible' | 'authfailed'
   room?: string
   latencyMs?: number
   attempt?: number
+  queued?: number
+  reason?: string
+}
+
+export type MediaAction =
+  | 'playPause'
+  | 'next'
+  | 'previous'
+  | 'stop'
+  | 'volumeUp'
+  | 'volumeDown'
+  | 'mute'
+
+export type MediaBackend = 'keys' | 'mpris' | 'mpv' | 'vlc' | 'browser'
+
+export interface MediaTarget {
+  kind: MediaBackend
+  enabled: boolean
+  /** Message types this backend reacts to; empty means all. */
+  types: string[]
+}
+
+export interface VlcSettings {
+  interface: 'http' | 'rc'
+  host: string
+  port: number
+  password: string
+}
+
+export interface MediaSettings {
+  player?: string
+  mpvSocket: string
+  vlc: VlcSettings
+}
+
+export interface BridgeStatus {
+  port: number
+  connections: number
+}
+
+export interface DriftSettings {
+  enabled: boolean
+  thresholdMs: number
+  intervalMs: number
+  follow?: string
+}
+
+export interface Hook {
+  command: string
+  args: string[]
+  enabled: boolean
+  /** Message types to run for; empty means every playback command. */
+  types: string[]
+}
+
+export interface HookSettings {
+  hooks: Hook[]
+  timeoutMs: number
+  maxConcurrent: number
+}
+
+export interface RoomState {
+  playing: boolean
+  position?: number
+  lastActor?: string
+  memberCount: number
+}
+
+export interface Member {
+  clientId: string
+  displayName?: string
+  lastSeen: number
 }
 
 const STORAGE_KEY = 'media-sync-settings'
@@ -26,6 +100,17 @@ interface Settings {
   room: string
   autoConnect: boolean
   hotkey: string
+  alignOnJoin?: boolean
+  roomToken?: string
+  /** Saved in plaintext by older versions; wiped on load. */
+  roomPassphrase?: string
+  encryptRoom?: boolean
+  media?: MediaSettings
+  /** Single backend from before fan-out; migrated into mediaTargets. */
+  mediaBackend?: MediaBackend
+  mediaTargets?: MediaTarget[]
+  drift?: DriftSettings
+  hooks?: HookSettings
 }
 
 function generateUUID(): string {
@@ -38,6 +123,10 @@ function loadSettings(): Settings {
     if (saved) {
       const parsed = JSON.parse(saved)
       if (parsed.room) {
+        if (parsed.roomPassphrase !== undefined) {
+          delete parsed.roomPassphrase
+          saveSettings(parsed)
+        }
         return parsed
       }
     }
@@ -46,7 +135,10 @@ function loadSettings(): Settings {
     serverUrl: 'ws://localhost:8080',
     room: generateUUID(),
     autoConnect: false,
-    hotkey: ''
+    hotkey: '',
+    alignOnJoin: false,
+    roomToken: '',
+    encryptRoom: false
   }
 }
 
@@ -61,8 +153,25 @@ export const useSyncStore = defineStore('sync', () => {
   const room = ref(settings.room)
   const autoConnect = ref(settings.autoConnect)
   const hotkey = ref(settings.hotkey)
+  const alignOnJoin = ref(settings.alignOnJoin ?? false)
+  const roomToken = ref(settings.roomToken ?? '')
+  // Kept in memory only, so the room's secret never lands on disk
+  const roomPassphrase = ref('')
+  const encryptRoom = ref(settings.encryptRoom ?? false)
+  const mediaSettings = ref<MediaSettings | null>(settings.media ?? null)
+  const mediaPlayers = ref<string[]>([])
+  const mediaTargets = ref<MediaTarget[]>(
+    settings.mediaTargets ??
+      (settings.mediaBackend ? [{ kind: settings.mediaBackend, enabled: true, types: [] }] : [])
+  )
+  const bridgeStatus = ref<BridgeStatus | null>(null)
+  const driftSettings = ref<DriftSettings | null>(settings.drift ?? null)
+  const hookSettings = ref<HookSettings | null>(settings.hooks ?? null)
   const connectionStatus = ref<ConnectionStatus>({ status: 'disconnected' })
   const eventLog = ref<SyncEvent[]>([])
+  const roomState = ref<RoomState | null>(null)
+  const members = ref<Member[]>([])
+  const displayName = ref('')
 
   const isConnected = computed(() => connectionStatus.value.status === 'connected')
   const isConnecting = computed(() =>
@@ -75,7 +184,14 @@ export const useSyncStore = defineStore('sync', () => {
       serverUrl: serverUrl.value,
       room: room.value,
       autoConnect: autoConnect.value,
-      hotkey: hotkey.value
+      hotkey: hotkey.value,
+      alignOnJoin: alignOnJoin.value,
+      roomToken: roomToken.value,
+      encryptRoom: encryptRoom.value,
+      media: mediaSettings.value ?? undefined,
+      mediaTargets: mediaTargets.value,
+      drift: driftSettings.value ?? undefined,
+      hooks: hookSettings.value ?? undefined
     })
   }
 
@@ -91,7 +207,10 @@ export const useSyncStore = defineStore('sync', () => {
       persistSettings()
       await invoke('connect', {
         serverUrl: serverUrl.value,
-        room: room.value
+        room: room.value,
+        token: roomToken.value || null,
+        passphrase: roomPassphrase.value || null,
+        encrypt: encryptRoom.value
       })
     } catch (e) {
       console.error('Failed to connect:', e)
@@ -114,6 +233,119 @@ export const useSyncStore = defineStore('sync', () => {
     }
   }
 
+  async function sendMediaAction(action: MediaAction) {
+    try {
+      await invoke('send_media_action', { action })
+    } catch (e) {
+      console.error(`Failed to send ${action}:`, e)
+    }
+  }
+
+  async function sendSeek(positionMs: number) {
+    try {
+      await invoke('send_seek', { positionMs })
+    } catch (e) {
+      console.error('Failed to send seek:', e)
+    }
+  }
+
+  async function setAlignOnJoin(enabled: boolean) {
+    try {
+      await invoke('set_align_on_join', { enabled })
+      alignOnJoin.value = enabled
+      persistSettings()
+    } catch (e) {
+      console.error('Failed to set align on join:', e)
+    }
+  }
+
+  async function updateDriftSettings(patch: Partial<DriftSettings>) {
+    if (!driftSettings.value) return
+    const next = { ...driftSettings.value, ...patch }
+    try {
+      await invoke('set_drift_settings', { settings: next })
+      driftSettings.value = next
+      persistSettings()
+    } catch (e) {
+      console.error('Failed to update drift settings:', e)
+    }
+  }
+
+  async function updateHookSettings(patch: Partial<HookSettings>) {
+    if (!hookSettings.value) return
+    const next = { ...hookSettings.value, ...patch }
+    try {
+      await invoke('set_hook_settings', { settings: next })
+      hookSettings.value = next
+      persistSettings()
+    } catch (e) {
+      console.error('Failed to update hook settings:', e)
+    }
+  }
+
+  async function updateHook(index: number, patch: Partial<Hook>) {
+    if (!hookSettings.value) return
+    const hooks = hookSettings.value.hooks.map((h, i) => (i === index ? { ...h, ...patch } : h))
+    await updateHookSettings({ hooks })
+  }
+
+  async function setDisplayName(name: string) {
+    try {
+      await invoke('set_display_name', { displayName: name || null })
+      displayName.value = name
+    } catch (e) {
+      console.error('Failed to set display name:', e)
+    }
+  }
+
+  async function refreshMediaPlayers() {
+    try {
+      mediaPlayers.value = await invoke<string[]>('list_media_players')
+    } catch (e) {
+      console.error('Failed to list media players:', e)
+    }
+  }
+
+  async function updateMediaSettings(patch: Partial<MediaSettings>) {
+    const current = mediaSettings.value ?? (await invoke<MediaSettings>('get_media_settings'))
+    const next = { ...current, ...patch }
+    try {
+      await invoke('set_media_settings', { settings: next })
+      mediaSettings.value = next
+      persistSettings()
+    } catch (e) {
+      console.error('Failed to update media settings:', e)
+    }
+  }
+
+  async function updateVlcSettings(patch: Partial<VlcSettings>) {
+    if (!mediaSettings.value) return
+    await updateMediaSettings({ vlc: { ...mediaSettings.value.vlc, ...patch } })
+  }
+
+  async function setMediaTargets(targets: MediaTarget[]) {
+    try {
+      await invoke('set_media_targets', { targets })
+      mediaTargets.value = targets
+      persistSettings()
+    } catch (e) {
+      console.error('Failed to set media backends:', e)
+    }
+  }
+
+  /** Changes one backend's entry, adding it if it isn't in the set yet. */
+  async function updateMediaTarget(kind: MediaBackend, patch: Partial<MediaTarget>) {
+    const existing = mediaTargets.value.find((t) => t.kind === kind)
+    const targets = existing
+      ? mediaTargets.value.map((t) => (t.kind === kind ? { ...t, ...patch } : t))
+      : [...mediaTargets.value, { kind, enabled: true, types: [], ...patch }]
+    await setMediaTargets(targets)
+  }
+
+  function isBackendEnabled(kind: MediaBackend): boolean {
+    return mediaTargets.value.some((t) => t.kind === kind && t.enabled)
+  }
+
   async function setHotkey(shortcut: string) {
     try {
       await invoke('set_hotkey', { shortcut })
@@ -126,16 +358,119 @@ export const useSyncStore = defineStore('sync', () => {
   }
 
   async function init() {
+    const identity = await invoke<{ clientId: string; displayName?: string } | null>('get_identity')
+    displayName.value = identity?.displayName ?? ''
+
     // Listen for connection status changes
     await listen<ConnectionStatus>('connection-status', (event) => {
       connectionStatus.value = event.payload
     })
 
     // Listen for sync events
-    await listen<{ type: string; timestamp: number; clientId?: string }>('sync-event', (event) => {
-      const { type, timestamp, clientId } = event.payload
-      const direction = clientId ? 'in' : 'out'
-      addEvent({ type, timestamp, clientId }, direction)
+    await listen<{
+      type: string
+      timestamp: number
+      clientId?: string
+      position?: number
+      origin: 'self' | 'remote'
+    }>('sync-event', (event) => {
+      const { type, timestamp, clientId, position, origin } = event.payload
+      const direction = origin === 'self' ? 'out' : 'in'
+      addEvent({ type, timestamp, clientId, position }, direction)
+    })
+
+    // Show remote commands we deliberately didn't act on
+    await listen<{ type: string; clientId?: string; reason: string; detail: string }>(
+      'sync-suppressed',
+      (event) => {
+        const { type, clientId, reason, detail } = event.payload
+        addEvent({ type, timestamp: Date.now(), clientId, suppressed: `${reason}: ${detail}` }, 'in')
+      }
+    )
+
+    // Encrypted messages we hold no matching key for
+    await listen<{ clientId?: string; seq?: number; reason: string }>(
+      'cannot-decrypt',
+      (event) => {
+        const { clientId, reason } = event.payload
+        addEvent({ type: 'encrypted', timestamp: Date.now(), clientId, suppressed: `cannot decrypt: ${reason}` }, 'in')
+      }
+    )
+
+    // A backend that couldn't carry out a command
+    await listen<{ backend: string; type: string; error: string }>('media-error', (event) => {
+      const { backend, type, error } = event.payload
+      addEvent({ type, timestamp: Date.now(), detail: `${backend} failed: ${error}` }, 'in')
+    })
+
+    // Hooks that ran for a sync event, and how they ended
+    await listen<{
+      command: string
+      type: string
+      direction: 'in' | 'out'
+      exitCode: number | null
+      stderr: string
+      error?: string
+    }>('hook-result', (event) => {
+      const { command, type, direction, exitCode, stderr, error } = event.payload
+      const name = command.split(/[\\/]/).pop()
+      const outcome = error ?? `exited ${exitCode ?? 'abnormally'}`
+      const detail = `hook ${name} ${outcome}${stderr ? `: ${stderr}` : ''}`
+      addEvent({ type, timestamp: Date.now(), detail }, direction)
+    })
+
+    // Seeks made to catch up with (or wait for) the room
+    await listen<{ driftMs: number; fromMs: number; toMs: number; reference?: string; peers: number }>(
+      'drift-corrected',
+      (event) => {
+        const { driftMs, toMs, reference } = event.payload
+        const amount = `${driftMs > 0 ? 'ahead' : 'behind'} by ${(Math.abs(driftMs) / 1000).toFixed(1)}s`
+        addEvent({ type: 'drift', timestamp: Date.now(), position: toMs, clientId: reference, detail: amount }, 'in')
+      }
+    )
+
+    // Listen for room snapshots
+    await listen<RoomState>('room-state', (event) => {
+      roomState.value = event.payload
+    })
+
+    await invoke('set_align_on_join', { enabled: alignOnJoin.value })
+    if (driftSettings.value) {
+      await updateDriftSettings({})
+    } else {
+      driftSettings.value = await invoke<DriftSettings>('get_drift_settings')
+    }
+    if (hookSettings.value) {
+      await updateHookSettings({})
+    } else {
+      hookSettings.value = await invoke<HookSettings>('get_hook_settings')
+    }
+    if (mediaSettings.value) {
+      await updateMediaSettings({})
+    } else {
+      mediaSettings.value = await invoke<MediaSettings>('get_media_settings')
+    }
+    await refreshMediaPlayers()
+    if (mediaTargets.value.length > 0) {
+      await setMediaTargets(mediaTargets.value)
+    } else {
+      mediaTargets.value = await invoke<MediaTarget[]>('get_media_targets')
+    }
+
+    // Browser extensions connecting to the local bridge
+    await listen<BridgeStatus>('bridge-status', (event) => {
+      bridgeStatus.value = event.payload
+    })
+    bridgeStatus.value = await invoke<BridgeStatus>('get_bridge_status')
+
+    // Keep the member list in sync with presence events
+    const refreshMembers = async () => {
+      members.value = await invoke<Member[]>('get_members')
+    }
+    await listen<Member>('member-joined', refreshMembers)
+    await listen<Member>('member-left', refreshMembers)
+    await listen<Member[]>('members', (event) => {
+      members.value = event.payload
     })
 
     // Set initial hotkey if configured
@@ -158,13 +493,39 @@ export const useSyncStore = defineStore('sync', () => {
     room,
     autoConnect,
     hotkey,
+    alignOnJoin,
+    roomToken,
+    roomPassphrase,
+    encryptRoom,
+    mediaSettings,
+    mediaPlayers,
+    mediaTargets,
+    bridgeStatus,
+    driftSettings,
+    hookSettings,
     connectionStatus,
     eventLog,
+    roomState,
+    members,
+    displayName,
     isConnected,
     isConnecting,
     connect,
     disconnect,
     sendToggle,
+    sendMediaAction,
+    sendSeek,
+    setAlignOnJoin,
+    updateDriftSettings,
+    updateHookSettings,
+    updateHook,
+    setDisplayName,
+    refreshMediaPlayers,
+    updateMediaSettings,
+    updateVlcSettings,
+    setMediaTargets,
+    updateMediaTarget,
+    isBackendEnabled,
     setHotkey,
     init,
     persistSettings